@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use crate::config;
+use crate::param_value::ParamValue;
+
+enum Line {
+    Option {
+        key: String,
+        enabled: bool,
+        /// Everything before the key, such as the "#" of disabled options
+        prefix: String,
+        value: Option<String>,
+        /// The whitespace and comment after the option
+        suffix: String,
+    },
+    Other(String),
+}
+
+/// The lines of an Arepo Config.sh, including disabled options and grouping
+/// comments, so that options can be changed where they already are.
+pub struct ArepoConfigFile {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+impl ArepoConfigFile {
+    pub fn parse(contents: &str) -> ArepoConfigFile {
+        ArepoConfigFile {
+            lines: contents.lines().map(parse_line).collect(),
+            trailing_newline: contents.ends_with('\n'),
+        }
+    }
+
+    /// The names of all options, both enabled and disabled.
+    pub fn option_names(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Option { key, .. } => Some(key.as_str()),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Enables, disables or changes the option. Disabled options are enabled where
+    /// they are commented out, options which do not appear at all are appended.
+    pub fn set(&mut self, key: &str, new_value: &ParamValue) {
+        let new_text = match new_value {
+            ParamValue::Bool(true) => None,
+            ParamValue::Bool(false) => {
+                self.disable(key);
+                return;
+            }
+            value => Some(value.to_string()),
+        };
+        let position = self.find(key, true).or_else(|| self.find(key, false));
+        match position {
+            Some(i) => {
+                if let Line::Option {
+                    enabled,
+                    prefix,
+                    value,
+                    ..
+                } = &mut self.lines[i]
+                {
+                    let unchanged = match (&value, &new_text) {
+                        (Some(value), Some(_)) => {
+                            ParamValue::from_str(value).ok().as_ref() == Some(new_value)
+                        }
+                        (None, None) => true,
+                        _ => false,
+                    };
+                    if !*enabled {
+                        *enabled = true;
+                        *prefix = "".into();
+                    }
+                    if !unchanged {
+                        *value = new_text;
+                    }
+                }
+            }
+            None => self.append(key, new_text),
+        }
+    }
+
+    fn disable(&mut self, key: &str) {
+        while let Some(i) = self.find(key, true) {
+            if let Line::Option {
+                enabled, prefix, ..
+            } = &mut self.lines[i]
+            {
+                *enabled = false;
+                *prefix = format!("#{}", prefix);
+            }
+        }
+    }
+
+    fn find(&self, key: &str, enabled: bool) -> Option<usize> {
+        self.lines.iter().position(|line| {
+            matches!(line, Line::Option { key: k, enabled: e, .. } if k == key && *e == enabled)
+        })
+    }
+
+    fn append(&mut self, key: &str, value: Option<String>) {
+        let has_section = self.lines.iter().any(
+            |line| matches!(line, Line::Other(l) if l == config::CONFIG_FILE_APPENDED_SECTION_HEADER),
+        );
+        if !has_section {
+            self.lines.push(Line::Other("".into()));
+            self.lines.push(Line::Other(
+                config::CONFIG_FILE_APPENDED_SECTION_HEADER.into(),
+            ));
+        }
+        self.lines.push(Line::Option {
+            key: key.into(),
+            enabled: true,
+            prefix: "".into(),
+            value,
+            suffix: "".into(),
+        });
+    }
+}
+
+impl std::fmt::Display for ArepoConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match line {
+                Line::Option {
+                    key,
+                    prefix,
+                    value,
+                    suffix,
+                    ..
+                } => {
+                    write!(f, "{}{}", prefix, key)?;
+                    if let Some(value) = value {
+                        write!(f, "={}", value)?;
+                    }
+                    write!(f, "{}", suffix)?;
+                }
+                Line::Other(line) => write!(f, "{}", line)?,
+            }
+        }
+        if self.trailing_newline {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses enabled options such as "SWEEP" or "CHEMISTRY_NETWORK=1" as well as
+/// options disabled by commenting them out such as "#SWEEP". Comment lines which
+/// do not look like an option are kept as they are.
+fn parse_line(line: &str) -> Line {
+    let trimmed = line.trim_start();
+    let (enabled, rest) = match trimmed.strip_prefix('#') {
+        Some(rest) => (false, rest.trim_start()),
+        None => (true, trimmed),
+    };
+    let key_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let key = &rest[..key_len];
+    let after_key = &rest[key_len..];
+    let looks_like_option = !key.is_empty()
+        && key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && (enabled || key.chars().all(|c| !c.is_ascii_lowercase()))
+        && (after_key.trim().is_empty()
+            || after_key.starts_with('=')
+            || after_key.trim_start().starts_with('#'));
+    if !looks_like_option {
+        return Line::Other(line.into());
+    }
+    let (value, suffix) = match after_key.strip_prefix('=') {
+        Some(value_and_suffix) => {
+            let value_len = value_and_suffix
+                .find(char::is_whitespace)
+                .unwrap_or(value_and_suffix.len());
+            (
+                Some(value_and_suffix[..value_len].to_owned()),
+                &value_and_suffix[value_len..],
+            )
+        }
+        None => (None, after_key),
+    };
+    Line::Option {
+        key: key.into(),
+        enabled,
+        prefix: line[..line.len() - rest.len()].into(),
+        value,
+        suffix: suffix.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_changed_in_place() {
+        let contents = "#--------- Radiative transfer\nSWEEP            # the sweep\n#SWEEP_WARMSTART\n# Some comment\nCHEMISTRY_NETWORK=1\n";
+        let mut file = ArepoConfigFile::parse(contents);
+        assert_eq!(file.to_string(), contents);
+        file.set("SWEEP", &ParamValue::Bool(false));
+        file.set("SWEEP_WARMSTART", &ParamValue::Bool(true));
+        file.set("CHEMISTRY_NETWORK", &ParamValue::Int(9));
+        file.set("PERIODIC", &ParamValue::Bool(true));
+        assert_eq!(
+            file.to_string(),
+            format!(
+                "#--------- Radiative transfer\n#SWEEP            # the sweep\nSWEEP_WARMSTART\n# Some comment\nCHEMISTRY_NETWORK=9\n\n{}\nPERIODIC\n",
+                config::CONFIG_FILE_APPENDED_SECTION_HEADER
+            )
+        );
+    }
+}
@@ -22,6 +22,13 @@ impl ArepoLogFile {
         read_file_contents(&self.file).context("While reading log file")
     }
 
+    /// Whether Arepo reached the final time of the run, according to the log file.
+    pub fn run_finished(&self) -> bool {
+        self.get_contents()
+            .map(|contents| contents.contains("Simulation ends"))
+            .unwrap_or(false)
+    }
+
     pub fn get_num_cores(&self) -> Result<i64> {
         let re = Regex::new("Running with ([0-9]+) MPI tasks").unwrap();
         let num_cores_string = self.get_first_capture_string(&re)?;
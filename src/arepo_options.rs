@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8Path;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::arepo_config_file::ArepoConfigFile;
+use crate::config;
+use crate::config_file::ConfigFile;
+use crate::sim_params::is_param_file_param;
+use crate::sim_set::SimSet;
+use crate::util::get_files_recursively;
+use crate::util::get_git_commit;
+use crate::util::read_file_contents;
+use crate::util::write_file;
+
+lazy_static! {
+    /// The options of the Arepo repository in the bob config, once they
+    /// have been loaded with `load_arepo_options`.
+    static ref AREPO_OPTIONS: RwLock<Option<ArepoOptions>> = RwLock::new(None);
+}
+
+/// The Config.sh options and parameters which the Arepo source tree knows about.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ArepoOptions {
+    pub config_options: BTreeSet<String>,
+    pub params: BTreeSet<String>,
+}
+
+impl ArepoOptions {
+    pub fn from_arepo_path(arepo_path: &Utf8Path) -> Result<ArepoOptions> {
+        let template_path = arepo_path.join(config::DEFAULT_AREPO_TEMPLATE_CONFIG_FILE);
+        let template = read_file_contents(&template_path)?;
+        let config_options = ArepoConfigFile::parse(&template)
+            .option_names()
+            .map(|name| name.to_owned())
+            .collect();
+        let source_folder = arepo_path.join(config::DEFAULT_AREPO_SOURCE_FOLDER);
+        let mut params = BTreeSet::new();
+        for file in get_files_recursively(&source_folder)
+            .with_context(|| format!("While reading Arepo sources at {}", source_folder))?
+        {
+            if matches!(file.extension(), Some("c") | Some("h")) {
+                params.extend(get_param_names_from_source(&read_file_contents(&file)?));
+            }
+        }
+        Ok(ArepoOptions {
+            config_options,
+            params,
+        })
+    }
+}
+
+/// Finds the parameter tags which Arepo registers for reading the param file.
+fn get_param_names_from_source(contents: &str) -> Vec<String> {
+    let re = Regex::new(r#"(?:strcpy\(\s*tag\[nt\],|add_param\()\s*"([^"]+)""#).unwrap();
+    re.captures_iter(contents)
+        .map(|cap| cap[1].to_string())
+        .collect()
+}
+
+/// Discovers the options of the Arepo repository in the bob config, so that names
+/// which are missing from the built-in lists are classified correctly. Until this
+/// is called, or if there is no config, only the built-in lists are used.
+pub fn load_arepo_options() {
+    let arepo_path = match ConfigFile::read().and_then(|config| config.expanduser()) {
+        Ok(config) => config.arepo_path,
+        Err(_) => return,
+    };
+    match get_cached_arepo_options(&arepo_path) {
+        Ok(options) => *AREPO_OPTIONS.write().unwrap() = Some(options),
+        Err(e) => eprintln!(
+            "Warning: Could not discover options from Arepo at {}, using built-in lists: {:?}",
+            arepo_path, e
+        ),
+    }
+}
+
+/// Reads the options for the current Arepo commit from the cache, discovering
+/// and caching them first if necessary. Without a commit, nothing is cached.
+fn get_cached_arepo_options(arepo_path: &Utf8Path) -> Result<ArepoOptions> {
+    let commit = match get_git_commit(arepo_path) {
+        Some(commit) => commit,
+        None => return ArepoOptions::from_arepo_path(arepo_path),
+    };
+    let xdg_dirs = xdg::BaseDirectories::with_prefix("bob")?;
+    let cache_file_name = format!("{}{}.yaml", config::AREPO_OPTIONS_CACHE_FILE_PREFIX, commit);
+    if let Some(cache_file) = xdg_dirs.find_cache_file(&cache_file_name) {
+        let contents = std::fs::read_to_string(&cache_file)?;
+        if let Ok(options) = serde_yaml::from_str(&contents) {
+            return Ok(options);
+        }
+    }
+    let options = ArepoOptions::from_arepo_path(arepo_path)?;
+    let cache_file = xdg_dirs.place_cache_file(&cache_file_name)?;
+    if let Some(cache_file) = Utf8Path::from_path(&cache_file) {
+        write_file(cache_file, &serde_yaml::to_string(&options)?)?;
+    }
+    Ok(options)
+}
+
+/// Whether the name is a Config.sh option, according to the built-in list
+/// or the options discovered from the Arepo source.
+pub fn is_config_option(name: &str) -> bool {
+    config::CONFIG_FILE_PARAMS.contains(&name)
+        || match AREPO_OPTIONS.read().unwrap().as_ref() {
+            Some(options) => options.config_options.contains(name),
+            None => false,
+        }
+}
+
+/// Whether the name is a param file parameter, according to the built-in
+/// list or the parameters discovered from the Arepo source.
+pub fn is_param(name: &str) -> bool {
+    config::PARAM_FILE_PARAMS.contains(&name)
+        || match AREPO_OPTIONS.read().unwrap().as_ref() {
+            Some(options) => options.params.contains(name),
+            None => false,
+        }
+}
+
+/// Warns about the param file parameters of the sims which the Arepo source does not
+/// know about. Without discovered options, nothing is reported, since the built-in
+/// list may be outdated.
+pub fn warn_about_unknown_params(sim_set: &SimSet) {
+    let options = AREPO_OPTIONS.read().unwrap();
+    let options = match options.as_ref() {
+        Some(options) => options,
+        None => return,
+    };
+    let unknown: BTreeSet<&str> = sim_set
+        .iter()
+        .flat_map(|sim| sim.keys())
+        .filter(|key| is_param_file_param(key) && !options.params.contains(*key))
+        .map(|key| key.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        eprintln!(
+            "Warning: Found parameters unknown to Arepo: {}",
+            unknown.iter().join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn param_names_from_source() {
+        let contents = r#"
+  strcpy(tag[nt], "InitCondFile");
+  addr[nt] = All.InitCondFile;
+  id[nt++] = STRING;
+#ifdef SWEEP
+  strcpy(tag[nt],"SweepMaxNumIterations");
+#endif
+  add_param("TimeMax", &All.TimeMax, PARAM_DOUBLE, PARAM_FIXED);
+"#;
+        assert_eq!(
+            get_param_names_from_source(contents),
+            ["InitCondFile", "SweepMaxNumIterations", "TimeMax"]
+        );
+    }
+}
@@ -1,5 +1,8 @@
+use bob::expression::Expr;
+use bob::param_override::ParamOverride;
 use bob::postprocess::postprocess_args::PostprocessArgs;
 use bob::postprocess::replot_args::ReplotArgs;
+use bob::search::SearchArgs;
 use bob::systype::Systype;
 use camino::Utf8PathBuf;
 use clap::Clap;
@@ -18,26 +21,51 @@ pub struct Opts {
 #[derive(Clap, Debug)]
 pub enum SubCommand {
     Show(ShowSimulationInfo),
+    Check(CheckBobFile),
     Diff(ShowSimulationDiff),
     ShowOutput(ShowSimulationInfoOutput),
     Copy(CopySimulation),
     Build(BuildSimulation),
     Run(RunSimulation),
     Start(StartSimulation),
+    Search(SearchArgs),
     Post(PostprocessArgs),
     Plot(PostprocessArgs),
     Replot(ReplotArgs),
     GetData(GetData),
+    FindExisting(FindExisting),
     CopyAbundances(CopyAbundances),
+    CascadeHandoff(CascadeHandoff),
 }
 
 /// Read the input directory and show info about the resulting simulations.
 #[derive(Clap, Debug)]
 pub struct ShowSimulationInfo {
     pub folder: Utf8PathBuf,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
     pub param_names: Vec<String>,
     #[clap(short, long)]
     pub all: bool,
+    /// Only use the sims for which this condition holds, e.g. 'numCores>=64 && SWEEP==true'
+    #[clap(long = "where")]
+    pub condition: Option<Expr>,
+    /// Override a substitution of the bob file, e.g. --set numCores=4,8,16
+    #[clap(long = "set", number_of_values = 1)]
+    pub overrides: Vec<ParamOverride>,
+    /// Vary each of the overridden parameters independently of all others
+    #[clap(long)]
+    pub cartesian: bool,
+}
+
+/// Check the bob file in the input directory and report all problems found in it.
+#[derive(Clap, Debug)]
+pub struct CheckBobFile {
+    pub folder: Utf8PathBuf,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
 }
 
 /// Show the difference in the parameters between two simulation directories
@@ -56,6 +84,9 @@ pub struct ShowSimulationInfoOutput {
     pub param_names: Vec<String>,
     #[clap(short, long)]
     pub all: bool,
+    /// Only use the sims for which this condition holds, e.g. 'numCores>=64 && SWEEP==true'
+    #[clap(long = "where")]
+    pub condition: Option<Expr>,
 }
 
 /// Read the input directory and copy/rewrite the simulation files
@@ -63,10 +94,22 @@ pub struct ShowSimulationInfoOutput {
 pub struct CopySimulation {
     pub input_folder: Utf8PathBuf,
     pub output_folder: Utf8PathBuf,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
     #[clap(short, long)]
     pub delete: bool,
+    /// Only add the sims that do not exist in the output folder yet
+    #[clap(short, long)]
+    pub extend: bool,
     #[clap(short, long)]
     pub symlink_ics: bool,
+    /// Override a substitution of the bob file, e.g. --set numCores=4,8,16
+    #[clap(long = "set", number_of_values = 1)]
+    pub overrides: Vec<ParamOverride>,
+    /// Vary each of the overridden parameters independently of all others
+    #[clap(long)]
+    pub cartesian: bool,
 }
 
 /// Build arepo for each of the configuration files in the output directory
@@ -74,12 +117,18 @@ pub struct CopySimulation {
 pub struct BuildSimulation {
     pub output_folder: Utf8PathBuf,
     pub systype: Option<Systype>,
+    /// Only use the sims for which this condition holds, e.g. 'numCores>=64 && SWEEP==true'
+    #[clap(long = "where")]
+    pub condition: Option<Expr>,
 }
 
 /// Run each of the simulations in the output directory
 #[derive(Clap, Debug)]
 pub struct RunSimulation {
     pub output_folder: Utf8PathBuf,
+    /// Only use the sims for which this condition holds, e.g. 'numCores>=64 && SWEEP==true'
+    #[clap(long = "where")]
+    pub condition: Option<Expr>,
 }
 
 /// Copy, Build and Run in one command
@@ -87,11 +136,33 @@ pub struct RunSimulation {
 pub struct StartSimulation {
     pub input_folder: Utf8PathBuf,
     pub output_folder: Utf8PathBuf,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
     #[clap(short, long)]
     pub delete: bool,
+    /// Only add, build and run the sims that do not exist in the output folder yet
+    #[clap(short, long)]
+    pub extend: bool,
     pub systype: Option<Systype>,
     #[clap(short, long)]
     pub symlink_ics: bool,
+    /// Override a substitution of the bob file, e.g. --set numCores=4,8,16
+    #[clap(long = "set", number_of_values = 1)]
+    pub overrides: Vec<ParamOverride>,
+    /// Vary each of the overridden parameters independently of all others
+    #[clap(long)]
+    pub cartesian: bool,
+}
+
+/// Look for existing runs of the simulations of the input directory in the output roots
+#[derive(Clap, Debug)]
+pub struct FindExisting {
+    pub input_folder: Utf8PathBuf,
+    pub output_roots: Vec<Utf8PathBuf>,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
 }
 
 /// Copy all the relevant files (snapshots and parameter files) from a simulation to another dir
@@ -113,3 +184,9 @@ pub struct CopyAbundances {
     pub sim_coordinates: Utf8PathBuf,
     pub snap_output: Utf8PathBuf,
 }
+
+/// Prepare the initial conditions of a cascade segment from the last snapshot of the previous segment
+#[derive(Clap, Debug)]
+pub struct CascadeHandoff {
+    pub sim_folder: Utf8PathBuf,
+}
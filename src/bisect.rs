@@ -0,0 +1,89 @@
+use anyhow::Result;
+
+/// The half of the interval in which the bisection continues.
+pub enum Bisection {
+    Lower,
+    Upper,
+    Done,
+}
+
+/// Bisects [x_min, x_max] until f(x) is within treshold of y_target,
+/// assuming f is increasing.
+pub fn bisect(f: impl Fn(f64) -> f64, y_target: f64, treshold: f64, x_min: f64, x_max: f64) -> f64 {
+    bisect_with(
+        |x| {
+            let y = f(x);
+            Ok(if (y - y_target).abs() < treshold {
+                Bisection::Done
+            } else if y > y_target {
+                Bisection::Lower
+            } else {
+                Bisection::Upper
+            })
+        },
+        x_min,
+        x_max,
+        15,
+    )
+    .unwrap()
+}
+
+/// Repeatedly halves [x_min, x_max], letting step decide at each midpoint
+/// which half to continue in. Calls step at most `max_depth` times and
+/// returns the last midpoint.
+pub fn bisect_with(
+    mut step: impl FnMut(f64) -> Result<Bisection>,
+    mut x_min: f64,
+    mut x_max: f64,
+    max_depth: usize,
+) -> Result<f64> {
+    let mut x_try = (x_max + x_min) / 2.0;
+    for _ in 0..max_depth {
+        x_try = (x_max + x_min) / 2.0;
+        match step(x_try)? {
+            Bisection::Done => return Ok(x_try),
+            Bisection::Lower => x_max = x_try,
+            Bisection::Upper => x_min = x_try,
+        }
+    }
+    Ok(x_try)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bisect_to_target() {
+        let x = bisect(|x| x * x, 2.0, 1e-6, 0.0, 2.0);
+        assert!((x - 2.0f64.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn bisect_respects_max_depth() -> Result<()> {
+        let mut num_steps = 0;
+        let x = bisect_with(
+            |_| {
+                num_steps += 1;
+                Ok(Bisection::Upper)
+            },
+            0.0,
+            1.0,
+            3,
+        )?;
+        assert_eq!(num_steps, 3);
+        assert_eq!(x, 0.875);
+        let mut num_steps = 0;
+        bisect_with(
+            |_| {
+                num_steps += 1;
+                Ok(Bisection::Done)
+            },
+            0.0,
+            1.0,
+            3,
+        )?;
+        assert_eq!(num_steps, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use camino::Utf8Path;
+use itertools::Itertools;
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+use yaml_rust::parser::Event;
+use yaml_rust::parser::MarkedEventReceiver;
+use yaml_rust::parser::Parser;
+use yaml_rust::scanner::Marker;
+
+use crate::expression::Expr;
+use crate::range_generator::RangeGenerator;
+use crate::sim_set::CartesianType;
+use crate::sim_set::BOB_FILE_MERGED_KEYS;
+use crate::util::read_file_contents;
+use crate::yaml_include::read_yaml_with_includes;
+
+static KNOWN_KEYS: &[&str] = &[
+    "cartesian_type",
+    "substitutions",
+    "derived",
+    "exclude",
+    "include_only",
+    "folder_name",
+    "replicas",
+    "seed_param",
+];
+
+/// A problem found in a bob file, with the key and line it refers to (if known).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BobFileProblem {
+    pub key: Option<String>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for BobFileProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Checks the bob file after resolving its includes. Line numbers refer to the file itself.
+pub fn check_bob_file(path: &Utf8Path) -> Result<Vec<BobFileProblem>> {
+    let contents = read_file_contents(path)?;
+    if let Err(problem) = parse_bob_file(&contents) {
+        return Ok(vec![problem]);
+    }
+    let value = read_yaml_with_includes(path, BOB_FILE_MERGED_KEYS)?;
+    Ok(check_bob_file_value(value, &contents))
+}
+
+/// Turns the problems found in a bob file into a single error listing all of them.
+pub fn problems_to_result(path: &Utf8Path, problems: &[BobFileProblem]) -> Result<()> {
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "Found {} problems in bob file {}:\n{}",
+            problems.len(),
+            path,
+            problems.iter().join("\n")
+        )),
+    }
+}
+
+pub fn check_bob_file_contents(contents: &str) -> Vec<BobFileProblem> {
+    match parse_bob_file(contents) {
+        Ok(value) => check_bob_file_value(value, contents),
+        Err(problem) => vec![problem],
+    }
+}
+
+fn parse_bob_file(contents: &str) -> Result<Value, BobFileProblem> {
+    serde_yaml::from_str(contents).map_err(|e| BobFileProblem {
+        key: None,
+        line: e.location().map(|location| location.line()),
+        message: e.to_string(),
+    })
+}
+
+fn check_bob_file_value(value: Value, contents: &str) -> Vec<BobFileProblem> {
+    let mut checker = Checker {
+        lines: get_key_lines(contents),
+        problems: vec![],
+    };
+    match value {
+        Value::Mapping(mapping) => checker.check(&mapping),
+        _ => checker.problem(&[], "Bob file should contain a mapping".into()),
+    }
+    checker.problems
+}
+
+struct Checker {
+    lines: HashMap<Vec<String>, usize>,
+    problems: Vec<BobFileProblem>,
+}
+
+impl Checker {
+    fn problem(&mut self, path: &[&str], message: String) {
+        let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+        self.problems.push(BobFileProblem {
+            key: path.last().cloned(),
+            line: self.lines.get(&path).copied(),
+            message,
+        });
+    }
+
+    fn check(&mut self, mapping: &Mapping) {
+        for (key, _) in mapping.iter() {
+            match key.as_str() {
+                Some(key) if KNOWN_KEYS.contains(&key) => {}
+                Some(key) => self.problem(&[key], "Unknown key".into()),
+                None => self.problem(&[], format!("Invalid key {:?}", key)),
+            }
+        }
+        let get = |key: &str| mapping.get(&Value::String(key.into()));
+        let cartesian_type = match get("cartesian_type") {
+            None => {
+                self.problem(&[], "Missing key: cartesian_type".into());
+                None
+            }
+            Some(value) => match serde_yaml::from_value::<CartesianType>(value.clone()) {
+                Ok(cartesian_type) => Some(cartesian_type),
+                Err(e) => {
+                    self.problem(
+                        &["cartesian_type"],
+                        format!("Invalid cartesian type: {}", e),
+                    );
+                    None
+                }
+            },
+        };
+        let substitutions = match get("substitutions") {
+            None => {
+                self.problem(&[], "Missing key: substitutions".into());
+                vec![]
+            }
+            Some(value) => self.check_substitutions(value),
+        };
+        if let Some(cartesian_type) = &cartesian_type {
+            self.check_lengths(cartesian_type, &substitutions);
+        }
+        if let Some(derived) = get("derived") {
+            self.check_derived(derived, &substitutions);
+        }
+        for key in ["exclude", "include_only"] {
+            if let Some(rules) = get(key) {
+                self.check_rules(key, rules);
+            }
+        }
+        if let Some(folder_name) = get("folder_name") {
+            if folder_name.as_str().is_none() {
+                self.problem(&["folder_name"], "Expected a string".into());
+            }
+        }
+        self.check_replicas(get("replicas"), get("seed_param"), &substitutions);
+        if let (Some(CartesianType::Cascade(_)), Some(_)) = (&cartesian_type, get("replicas")) {
+            self.problem(&["replicas"], "Cannot be used with cascade sim sets".into());
+        }
+    }
+
+    fn check_replicas(
+        &mut self,
+        replicas: Option<&Value>,
+        seed_param: Option<&Value>,
+        substitutions: &[(String, Option<usize>)],
+    ) {
+        if let Some(replicas) = replicas {
+            if !matches!(replicas.as_u64(), Some(n) if n > 0) {
+                self.problem(&["replicas"], "Expected a positive number".into());
+            }
+        }
+        if let Some(seed_param) = seed_param {
+            match seed_param.as_str() {
+                None => self.problem(&["seed_param"], "Expected a parameter name".into()),
+                Some(seed_param) if substitutions.iter().any(|(k, _)| k == seed_param) => self
+                    .problem(
+                        &["seed_param"],
+                        format!(
+                            "Seed parameter {} also appears in substitutions",
+                            seed_param
+                        ),
+                    ),
+                Some(_) => {}
+            }
+            if replicas.is_none() {
+                self.problem(&["seed_param"], "Given without replicas".into());
+            }
+        }
+    }
+
+    /// Checks the individual substitution values and returns the
+    /// number of values (if given as a list) for each parameter.
+    fn check_substitutions(&mut self, value: &Value) -> Vec<(String, Option<usize>)> {
+        let mapping = match value {
+            Value::Mapping(mapping) => mapping,
+            Value::Null => return vec![],
+            _ => {
+                self.problem(&["substitutions"], "Expected a mapping".into());
+                return vec![];
+            }
+        };
+        let mut result = vec![];
+        for (key, value) in mapping.iter() {
+            let key = match key.as_str() {
+                Some(key) => key,
+                None => {
+                    self.problem(&["substitutions"], format!("Invalid key {:?}", key));
+                    continue;
+                }
+            };
+            let path = ["substitutions", key];
+            let length = match value {
+                Value::Null => {
+                    self.problem(&path, "Missing value".into());
+                    None
+                }
+                Value::Mapping(_) => {
+                    match serde_yaml::from_value::<RangeGenerator>(value.clone())
+                        .map_err(anyhow::Error::from)
+                        .and_then(|generator| generator.values())
+                    {
+                        Ok(values) => Some(values.len()),
+                        Err(e) => {
+                            self.problem(&path, format!("Invalid range generator: {}", e));
+                            None
+                        }
+                    }
+                }
+                Value::Sequence(values) => {
+                    for (i, value) in values.iter().enumerate() {
+                        if !is_scalar_or_value_list(value) {
+                            self.problem(
+                                &path,
+                                format!(
+                                    "Entry {} of list is not a single value or a list value such as [1, 2] in [[1, 2]]: {:?}",
+                                    i, value
+                                ),
+                            );
+                        }
+                    }
+                    Some(values.len())
+                }
+                _ => None,
+            };
+            result.push((key.to_owned(), length));
+        }
+        result.sort();
+        result
+    }
+
+    fn check_lengths(
+        &mut self,
+        cartesian_type: &CartesianType,
+        substitutions: &[(String, Option<usize>)],
+    ) {
+        let groups: Vec<Vec<String>> = match cartesian_type {
+            CartesianType::NoCartesian => {
+                vec![substitutions.iter().map(|(k, _)| k.clone()).collect()]
+            }
+            CartesianType::Grouped(groups) => {
+                self.check_groups(groups, substitutions);
+                groups.clone()
+            }
+            CartesianType::Sample(_) => {
+                for (key, length) in substitutions.iter() {
+                    if length.is_some() {
+                        self.problem(
+                            &["substitutions", key],
+                            "Substitutions of a sampled sim set may not contain lists".into(),
+                        );
+                    }
+                }
+                vec![]
+            }
+            CartesianType::All | CartesianType::Cascade(_) => vec![],
+        };
+        for group in groups.iter() {
+            let lengths: Vec<(&String, usize)> = substitutions
+                .iter()
+                .filter(|(k, _)| group.contains(k))
+                .filter_map(|(k, length)| length.map(|length| (k, length)))
+                .collect();
+            let mut counts: HashMap<usize, usize> = HashMap::new();
+            for (_, length) in lengths.iter() {
+                *counts.entry(*length).or_default() += 1;
+            }
+            // The most common length is taken as the expected one. In case of a tie,
+            // the first parameter (in alphabetical order) decides.
+            let reference = lengths
+                .iter()
+                .rev()
+                .max_by_key(|(_, length)| counts[length]);
+            if let Some((reference, expected)) = reference {
+                let expected = *expected;
+                for (key, length) in lengths.iter().filter(|(_, l)| *l != expected) {
+                    self.problem(
+                        &["substitutions", key],
+                        format!(
+                            "Expected list of length {} (like {}), found length {}",
+                            expected, reference, length
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_groups(&mut self, groups: &[Vec<String>], substitutions: &[(String, Option<usize>)]) {
+        let mut seen = vec![];
+        for (i, group) in groups.iter().enumerate() {
+            for member in group.iter() {
+                if !substitutions.iter().any(|(k, _)| k == member) {
+                    self.problem(
+                        &["cartesian_type"],
+                        format!(
+                            "Unknown parameter {} in group {} (not in substitutions)",
+                            member, i
+                        ),
+                    );
+                }
+                if seen.contains(&member) {
+                    self.problem(
+                        &["cartesian_type"],
+                        format!("Parameter {} appears in more than one group", member),
+                    );
+                }
+                seen.push(member);
+            }
+        }
+        for (key, _) in substitutions.iter() {
+            if !seen.contains(&key) {
+                self.problem(
+                    &["substitutions", key],
+                    "Parameter is not listed in any of the parameter groups".into(),
+                );
+            }
+        }
+    }
+
+    fn check_derived(&mut self, derived: &Value, substitutions: &[(String, Option<usize>)]) {
+        let mapping = match derived {
+            Value::Mapping(mapping) => mapping,
+            _ => {
+                self.problem(&["derived"], "Expected a mapping".into());
+                return;
+            }
+        };
+        for (key, expr) in mapping.iter() {
+            let key = match key.as_str() {
+                Some(key) => key,
+                None => {
+                    self.problem(&["derived"], format!("Invalid key {:?}", key));
+                    continue;
+                }
+            };
+            let path = ["derived", key];
+            match expr.as_str().map(|expr| expr.parse::<Expr>()) {
+                None => self.problem(&path, "Expected an expression string".into()),
+                Some(Err(e)) => self.problem(&path, format!("Invalid expression: {}", e)),
+                Some(Ok(_)) => {}
+            }
+            if substitutions.iter().any(|(k, _)| k == key) {
+                self.problem(
+                    &path,
+                    "Parameter appears both in substitutions and derived parameters".into(),
+                );
+            }
+        }
+    }
+
+    fn check_rules(&mut self, key: &str, rules: &Value) {
+        let rules = match rules {
+            Value::Sequence(rules) => rules,
+            _ => {
+                self.problem(&[key], "Expected a list of rules".into());
+                return;
+            }
+        };
+        for rule in rules.iter() {
+            match rule.as_str().map(|rule| rule.parse::<Expr>()) {
+                None => self.problem(&[key], format!("Expected a rule string: {:?}", rule)),
+                Some(Err(e)) => self.problem(&[key], format!("Invalid rule: {}", e)),
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::Bool(_) | Value::Number(_) | Value::String(_))
+}
+
+/// A list inside the list of values of a substitution is the value of a list parameter
+fn is_scalar_or_value_list(value: &Value) -> bool {
+    match value {
+        Value::Sequence(values) => values.iter().all(is_scalar),
+        value => is_scalar(value),
+    }
+}
+
+/// Finds the line on which each key (given by its path of parent keys) is defined.
+fn get_key_lines(contents: &str) -> HashMap<Vec<String>, usize> {
+    let mut collector = KeyLineCollector::default();
+    let mut parser = Parser::new(contents.chars());
+    // Syntax errors are reported by serde_yaml already
+    let _ = parser.load(&mut collector, false);
+    collector.lines
+}
+
+enum Container {
+    Mapping {
+        expecting_key: bool,
+        current_key: Option<String>,
+    },
+    Sequence,
+}
+
+#[derive(Default)]
+struct KeyLineCollector {
+    stack: Vec<Container>,
+    path: Vec<String>,
+    lines: HashMap<Vec<String>, usize>,
+}
+
+impl KeyLineCollector {
+    fn value_done(&mut self) {
+        if let Some(Container::Mapping { expecting_key, .. }) = self.stack.last_mut() {
+            *expecting_key = true;
+        }
+    }
+
+    fn start_container(&mut self, container: Container) {
+        let parent_key = match self.stack.last_mut() {
+            Some(Container::Mapping {
+                expecting_key: false,
+                current_key,
+            }) => current_key.clone().unwrap_or_default(),
+            Some(_) => "-".into(),
+            None => "".into(),
+        };
+        if !self.stack.is_empty() {
+            self.path.push(parent_key);
+        }
+        self.stack.push(container);
+    }
+
+    fn end_container(&mut self) {
+        self.stack.pop();
+        if !self.stack.is_empty() {
+            self.path.pop();
+        }
+        self.value_done();
+    }
+}
+
+impl MarkedEventReceiver for KeyLineCollector {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => match self.stack.last_mut() {
+                Some(Container::Mapping {
+                    expecting_key,
+                    current_key,
+                }) if *expecting_key => {
+                    *expecting_key = false;
+                    *current_key = Some(value.clone());
+                    let mut path = self.path.clone();
+                    path.push(value);
+                    self.lines.entry(path).or_insert_with(|| mark.line());
+                }
+                _ => self.value_done(),
+            },
+            Event::Alias(_) => self.value_done(),
+            Event::MappingStart(_) => self.start_container(Container::Mapping {
+                expecting_key: true,
+                current_key: None,
+            }),
+            Event::SequenceStart(_) => self.start_container(Container::Sequence),
+            Event::MappingEnd | Event::SequenceEnd => self.end_container(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_problems_are_reported() {
+        let contents = "cartesian_type:
+  Grouped:
+    - [a, b]
+    - [c, d]
+substitutions:
+  a: [1, 2]
+  b: [1, 2, 3]
+  c: [4, 5]
+  e: ~
+derived:
+  f: 'a +'
+  1: 'a + c'
+";
+        let problems = check_bob_file_contents(contents);
+        let find = |key: &str| {
+            problems
+                .iter()
+                .find(|problem| problem.key.as_deref() == Some(key))
+                .unwrap_or_else(|| panic!("No problem for key {}", key))
+        };
+        assert_eq!(find("b").line, Some(7));
+        assert!(find("b").message.contains("length 3"));
+        assert_eq!(find("e").line, Some(9));
+        assert_eq!(find("f").line, Some(11));
+        assert!(find("derived").message.contains("Invalid key"));
+        assert!(problems
+            .iter()
+            .any(|problem| problem.line == Some(1)
+                && problem.message.contains("Unknown parameter d")));
+    }
+
+    #[test]
+    fn valid_bob_file() {
+        let contents = "cartesian_type: All
+substitutions:
+  a: [1, 2]
+  b: {logspace: [1, 100, 3]}
+  rates: [[1, 2, 3]]
+exclude:
+  - 'a == 1 && b > 10'
+";
+        assert_eq!(check_bob_file_contents(contents), vec![]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::fs;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+
+use crate::config;
+use crate::copy_abundances::write_remapped_snapshot;
+use crate::postprocess::get_snapshot_files;
+use crate::postprocess::snapshot::Snapshot;
+use crate::sim_params::SimParams;
+use crate::sim_params::SimParamsKind;
+use crate::sim_set_manifest::SimSetManifest;
+use crate::util::copy_file;
+
+/// Prepares the initial conditions of a cascade segment from the last
+/// snapshot of the previous segment. The ICs are only replaced once
+/// they have been written completely.
+pub fn cascade_handoff(sim_folder: &Utf8Path) -> Result<()> {
+    let sim_folder = canonicalize(sim_folder)?;
+    let sim = SimParams::from_folder(&sim_folder, SimParamsKind::Output)?;
+    if !sim.get_default_bool(config::CASCADE_IDENTIFIER, false) {
+        return Err(anyhow!("Sim at {} is not part of a cascade", sim_folder));
+    }
+    let previous_folder = match get_previous_segment_folder(&sim_folder)? {
+        Some(folder) => folder,
+        None => {
+            println!("First cascade segment, nothing to hand off");
+            return Ok(());
+        }
+    };
+    let previous_sim = SimParams::from_folder(&previous_folder, SimParamsKind::Output)?;
+    let previous_snap_path = get_last_snapshot_path(&previous_sim)?;
+    let previous_snap = Snapshot::from_file(&previous_sim, &previous_snap_path)?;
+    check_snapshot_complete(&previous_snap).with_context(|| {
+        format!(
+            "Last snapshot of previous segment is incomplete: {}",
+            previous_snap_path
+        )
+    })?;
+    let ics_path = sim_folder.join(sim.get_ics_filename());
+    let temporary_path = ics_path.with_extension("handoff.hdf5");
+    println!("Handing off {} to {}", previous_snap_path, ics_path);
+    if sim.get_default_bool(config::ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER, false) {
+        copy_file(&previous_snap_path, &temporary_path)?;
+    } else {
+        let ics_snap = Snapshot::from_file(&sim, &ics_path)?;
+        write_remapped_snapshot(previous_snap, ics_snap, &temporary_path)
+            .with_context(|| format!("While remapping abundances onto {}", ics_path))?;
+    }
+    fs::rename(&temporary_path, &ics_path)
+        .with_context(|| format!("While moving {} to {}", temporary_path, ics_path))
+}
+
+fn canonicalize(folder: &Utf8Path) -> Result<Utf8PathBuf> {
+    let path = folder
+        .canonicalize()
+        .with_context(|| format!("Sim folder not found: {}", folder))?;
+    Utf8PathBuf::from_path_buf(path).map_err(|path| anyhow!("Invalid sim folder: {:?}", path))
+}
+
+/// Finds the folder of the previous segment, using the manifest of the
+/// sim set if there is one and the numbering of the folders otherwise.
+fn get_previous_segment_folder(sim_folder: &Utf8Path) -> Result<Option<Utf8PathBuf>> {
+    let output_folder = sim_folder
+        .parent()
+        .ok_or_else(|| anyhow!("Sim folder has no parent: {}", sim_folder))?;
+    let name = sim_folder.file_name().unwrap();
+    let previous_name = match SimSetManifest::read(output_folder)? {
+        Some(manifest) => {
+            let index = manifest
+                .sims
+                .iter()
+                .find(|entry| entry.name == name)
+                .ok_or_else(|| anyhow!("Sim {} not found in sim set manifest", name))?
+                .index;
+            if index == 0 {
+                return Ok(None);
+            }
+            manifest
+                .sims
+                .iter()
+                .find(|entry| entry.index == index - 1)
+                .ok_or_else(|| anyhow!("Previous segment of sim {} not found in manifest", name))?
+                .name
+                .clone()
+        }
+        None => match name.parse::<usize>() {
+            Ok(0) => return Ok(None),
+            Ok(index) => (index - 1).to_string(),
+            Err(_) => {
+                return Err(anyhow!(
+                    "Cannot determine previous segment of sim {} without a sim set manifest",
+                    name
+                ))
+            }
+        },
+    };
+    let previous_folder = output_folder.join(previous_name);
+    if !previous_folder.is_dir() {
+        return Err(anyhow!(
+            "Folder of previous segment not found: {}",
+            previous_folder
+        ));
+    }
+    Ok(Some(previous_folder))
+}
+
+fn get_last_snapshot_path(sim: &SimParams) -> Result<Utf8PathBuf> {
+    get_snapshot_files(sim)?
+        .last()
+        .ok_or_else(|| anyhow!("No snapshots found in previous segment {}", sim.folder))
+}
+
+fn check_snapshot_complete(snap: &Snapshot) -> Result<()> {
+    check_dataset_lengths(&snap.read_gas_dataset_lengths()?)
+}
+
+fn check_dataset_lengths(lengths: &[(String, usize)]) -> Result<()> {
+    let (first_name, first_length) = lengths
+        .first()
+        .ok_or_else(|| anyhow!("No gas datasets in snapshot"))?;
+    match lengths.iter().find(|(_, length)| length != first_length) {
+        Some((name, length)) => Err(anyhow!(
+            "Dataset {} has {} entries but {} has {}",
+            name,
+            length,
+            first_name,
+            first_length
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::param_value::ParamValue;
+    use crate::sim_set_manifest::ManifestEntry;
+    use crate::test_utils::sim_params_in;
+
+    fn temp_folder() -> (TempDir, Utf8PathBuf) {
+        let dir = TempDir::new("bob_cascade_handoff").unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().to_owned()).unwrap();
+        (dir, path)
+    }
+
+    fn create_folders(output_folder: &Utf8Path, names: &[&str]) {
+        for name in names {
+            fs::create_dir(output_folder.join(name)).unwrap();
+        }
+    }
+
+    #[test]
+    fn previous_segment_from_numbered_folders() -> Result<()> {
+        let (_dir, output_folder) = temp_folder();
+        create_folders(&output_folder, &["0", "1", "3", "segment"]);
+        assert_eq!(get_previous_segment_folder(&output_folder.join("0"))?, None);
+        assert_eq!(
+            get_previous_segment_folder(&output_folder.join("1"))?,
+            Some(output_folder.join("0"))
+        );
+        assert!(get_previous_segment_folder(&output_folder.join("3")).is_err());
+        assert!(get_previous_segment_folder(&output_folder.join("segment")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn previous_segment_from_manifest() -> Result<()> {
+        let (_dir, output_folder) = temp_folder();
+        create_folders(&output_folder, &["early", "late", "0"]);
+        let entry = |index, name: &str| ManifestEntry {
+            index,
+            name: name.into(),
+            varying_params: BTreeMap::new(),
+        };
+        let manifest = SimSetManifest {
+            sims: vec![entry(0, "early"), entry(1, "late")],
+            ..Default::default()
+        };
+        manifest.write(&output_folder)?;
+        assert_eq!(
+            get_previous_segment_folder(&output_folder.join("early"))?,
+            None
+        );
+        assert_eq!(
+            get_previous_segment_folder(&output_folder.join("late"))?,
+            Some(output_folder.join("early"))
+        );
+        // With a manifest, the folder numbering is not used
+        assert!(get_previous_segment_folder(&output_folder.join("0")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn last_snapshot_is_selected() -> Result<()> {
+        let (_dir, sim_folder) = temp_folder();
+        let output = sim_folder.join("output");
+        fs::create_dir(&output)?;
+        for file in [
+            "snap_000.hdf5",
+            "snap_010.hdf5",
+            "snap_002.hdf5",
+            "snap_011.hdf5.tmp",
+            "stdout.log",
+        ] {
+            fs::write(output.join(file), "")?;
+        }
+        let sim = sim_params_in(
+            &sim_folder,
+            SimParamsKind::Output,
+            &[("OutputDir", ParamValue::Str("output".into()))],
+        );
+        assert_eq!(get_last_snapshot_path(&sim)?, output.join("snap_010.hdf5"));
+        Ok(())
+    }
+
+    #[test]
+    fn dataset_lengths_must_agree() {
+        let lengths = |l: &[(&str, usize)]| -> Vec<(String, usize)> {
+            l.iter().map(|(n, l)| (n.to_string(), *l)).collect()
+        };
+        assert!(check_dataset_lengths(&lengths(&[("Coordinates", 8), ("Masses", 8)])).is_ok());
+        assert!(check_dataset_lengths(&lengths(&[("Coordinates", 8), ("Masses", 5)])).is_err());
+        assert!(check_dataset_lengths(&[]).is_err());
+    }
+}
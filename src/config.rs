@@ -16,21 +16,37 @@ lazy_static! {
 }
 
 pub static DEFAULT_BOB_CONFIG_NAME: &str = "sims.bob";
+/// Named sim sets are defined in files called sims.<name>.bob
+pub static NAMED_BOB_CONFIG_PREFIX: &str = "sims.";
+pub static NAMED_BOB_CONFIG_SUFFIX: &str = ".bob";
+/// Key in bob files and bob parameter files naming the files to include
+pub static INCLUDE_KEY: &str = "include";
 pub static DEFAULT_PARAM_FILE_NAME: &str = "param.txt";
+/// Starts the section of parameters that are written to a param file but were not in the original one
+pub static PARAM_FILE_APPENDED_SECTION_HEADER: &str = "%---- Parameters added by bob";
 pub static DEFAULT_CONFIG_FILE_NAME: &str = "Config.sh";
+/// Starts the section of options that are written to a Config.sh but were not in the original one
+pub static CONFIG_FILE_APPENDED_SECTION_HEADER: &str = "#---- Options added by bob";
 pub static DEFAULT_JOB_FILE_NAME: &str = "job";
 pub static DEFAULT_GRID_JOB_FILE_NAME: &str = "gridJob";
 pub static DEFAULT_GRID_FILE_NAME: &str = "grid.dat";
 pub static DEFAULT_BOB_PARAM_FILE_NAME: &str = "bobParams.yaml";
+pub static DEFAULT_SIM_HASH_FILE_NAME: &str = "bobSimHash";
+pub static DEFAULT_SIM_SET_MANIFEST_NAME: &str = "bobset.yaml";
 
 pub static DEFAULT_AREPO_EXECUTABLE_NAME: &str = "./Arepo";
 pub static DEFAULT_AREPO_SOURCE_FOLDER: &str = "src";
 pub static DEFAULT_AREPO_CONFIG_BUILD_FILE: &str = "build/arepoconfig.h";
 pub static DEFAULT_AREPO_CONFIG_SOURCE_FILE: &str = "src/arepoconfig.h";
+pub static DEFAULT_AREPO_TEMPLATE_CONFIG_FILE: &str = "Template-Config.sh";
+/// The options discovered from an Arepo commit are cached in arepo_options_<commit>.yaml
+pub static AREPO_OPTIONS_CACHE_FILE_PREFIX: &str = "arepo_options_";
 
 pub static DEFAULT_PIC_FOLDER: &str = "pics";
 
 pub static CONFIG_FILE_NAME: &str = "config.yaml";
+/// Additional option dependency rules in the bob config folder
+pub static OPTION_RULES_FILE_NAME: &str = "option_rules.yaml";
 
 pub static MAX_NUM_VORONOI_SWIM_THREADS: usize = 8;
 pub static MAX_NUM_POST_THREADS: usize = 1;
@@ -54,6 +70,12 @@ pub static DEFAULT_PLOT_CONFIG_FOLDER_NAME: &str = "config";
 pub static DEFAULT_PLOT_INFO_FILE_NAME: &str = "plot.info";
 
 pub static CASCADE_IDENTIFIER: &str = "cascade";
+pub static ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER: &str = "icsFromPreviousSegment";
+pub static PREVIOUS_SEGMENT_ICS_FILE_NAME: &str = "ics_previous_segment";
+pub static SAMPLE_SEED_IDENTIFIER: &str = "sampleSeed";
+pub static FOLDER_NAME_IDENTIFIER: &str = "folderName";
+pub static REPLICA_IDENTIFIER: &str = "replica";
+pub static REPLICA_SEED_PARAM_IDENTIFIER: &str = "replicaSeedParam";
 
 pub static SPECIAL_PARAMS: &[&str] = &[
     "numCores",
@@ -67,6 +89,11 @@ pub static SPECIAL_PARAMS: &[&str] = &[
     "wallTime",
     "simType",
     "simLabel",
+    "sampleSeed",
+    "folderName",
+    "icsFromPreviousSegment",
+    "replica",
+    "replicaSeedParam",
 ];
 
 pub static CALC_PARAMS: &[&str] = &["timeUnit"];
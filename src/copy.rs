@@ -1,21 +1,56 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
 
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
 use camino::Utf8Path;
+use itertools::Itertools;
 
 use crate::config;
+use crate::option_rules::check_option_rules;
+use crate::param_value::ParamValue;
+use crate::sim_hash::get_sim_hash;
+use crate::sim_hash::warn_about_duplicates_in_output;
+use crate::sim_hash::write_sim_hash;
+use crate::sim_params::get_bob_param_file_path;
+use crate::sim_params::is_config_file_param;
+use crate::sim_params::is_param_file_param;
+use crate::sim_params::read_bob_param_file;
 use crate::sim_params::SimParams;
 use crate::sim_set::SimSet;
+use crate::sim_set_manifest::ManifestEntry;
+use crate::sim_set_manifest::SimSetManifest;
+use crate::sim_set_manifest::SimSetSource;
+use crate::strfmt_utils::strfmt_anyhow;
 
 pub fn copy_sim_set<U: AsRef<Utf8Path>>(
     sim_set: &SimSet,
     input_folder: U,
     output_folder: U,
+    source: &SimSetSource,
     delete: bool,
+    extend: bool,
     symlink_ics: bool,
 ) -> Result<SimSet> {
     let output_folder = output_folder.as_ref();
+    if delete && extend {
+        return Err(anyhow!("Cannot both delete and extend the output folder"));
+    }
+    check_option_rules(sim_set)?;
+    if extend && output_folder.is_dir() {
+        let new_sim_set = extend_sim_set(
+            sim_set,
+            input_folder.as_ref(),
+            source,
+            output_folder,
+            symlink_ics,
+        )?;
+        warn_about_duplicates_in_output(&new_sim_set);
+        return Ok(new_sim_set);
+    }
+    let manifest = get_manifest(sim_set, input_folder.as_ref(), source)?;
     if delete && output_folder.is_dir() {
         fs::remove_dir_all(output_folder)
             .with_context(|| "When deleting the previous output folder")?;
@@ -23,9 +58,10 @@ pub fn copy_sim_set<U: AsRef<Utf8Path>>(
     fs::create_dir(output_folder).with_context(|| "When creating the output folder")?;
     let output_sim_set: Result<SimSet> = sim_set
         .enumerate()
-        .map(|(i, sim)| -> Result<(usize, SimParams)> {
+        .zip(manifest.sims.iter())
+        .map(|((i, sim), entry)| -> Result<(usize, SimParams)> {
             println!("Copying files for sim {}:", i);
-            let sim_output_folder = output_folder.join(i.to_string());
+            let sim_output_folder = output_folder.join(&entry.name);
             Ok((
                 *i,
                 copy_sim(sim, input_folder.as_ref(), &sim_output_folder, symlink_ics)
@@ -33,7 +69,217 @@ pub fn copy_sim_set<U: AsRef<Utf8Path>>(
             ))
         })
         .collect();
-    output_sim_set
+    let output_sim_set = output_sim_set?;
+    manifest.write(output_folder)?;
+    warn_about_duplicates_in_output(&output_sim_set);
+    Ok(output_sim_set)
+}
+
+/// Copies only those sims of the sim set that do not exist in the output folder yet.
+/// Existing sims are identified by the values of the parameters that vary within the
+/// sim set or within the existing sims. Returns the newly created sims.
+fn extend_sim_set(
+    sim_set: &SimSet,
+    input_folder: &Utf8Path,
+    source: &SimSetSource,
+    output_folder: &Utf8Path,
+    symlink_ics: bool,
+) -> Result<SimSet> {
+    let existing_sims = SimSet::from_output_folder(output_folder)?;
+    let existing_params: Vec<ExistingSim> = existing_sims
+        .enumerate()
+        .map(|(i, sim)| {
+            let params = read_bob_param_file(&get_bob_param_file_path(&sim.folder))?;
+            Ok((*i, sim.get_name(), params))
+        })
+        .collect::<Result<_>>()?;
+    let existing_manifest = SimSetManifest::read(output_folder)?;
+    let existing_varying_params: Vec<String> = match &existing_manifest {
+        Some(manifest) => manifest
+            .sims
+            .iter()
+            .flat_map(|entry| entry.varying_params.keys().cloned())
+            .collect(),
+        None => get_varying_params(&existing_params),
+    };
+    let identity_params = get_identity_params(sim_set, &existing_varying_params);
+    let new_sims = find_new_sims(sim_set, &existing_params, &identity_params)?;
+    let existing_entries = match existing_manifest {
+        Some(manifest) => manifest.sims,
+        None => existing_params
+            .iter()
+            .map(|(index, name, params)| ManifestEntry {
+                index: *index,
+                name: name.clone(),
+                varying_params: identity_params
+                    .iter()
+                    .filter_map(|k| params.get(k).map(|v| (k.clone(), v.clone())))
+                    .collect(),
+            })
+            .collect(),
+    };
+    let mut manifest = SimSetManifest::new(input_folder, source, existing_entries)?;
+    let first_new_index = manifest
+        .sims
+        .iter()
+        .map(|entry| entry.index + 1)
+        .max()
+        .unwrap_or(0);
+    let mut output_sims = vec![];
+    for (index, sim) in (first_new_index..).zip(new_sims) {
+        let name = get_sim_folder_name(index, sim)?;
+        let sim_output_folder = output_folder.join(&name);
+        if sim_output_folder.exists() {
+            return Err(anyhow!(
+                "Folder of new sim {} already exists: {:?}",
+                index,
+                sim_output_folder
+            ));
+        }
+        println!("Copying files for new sim {}:", index);
+        let output_sim = copy_sim(sim, input_folder, &sim_output_folder, symlink_ics)
+            .with_context(|| format!("When copying simulation {}", index))?;
+        manifest
+            .sims
+            .push(ManifestEntry::new(index, name, sim, sim_set));
+        output_sims.push((index, output_sim));
+    }
+    println!(
+        "Created {} new sims, {} sims already existed",
+        output_sims.len(),
+        sim_set.len() - output_sims.len()
+    );
+    manifest.write(output_folder)?;
+    Ok(output_sims.into_iter().collect())
+}
+
+/// The index, name and parameters of a sim in the output folder
+type ExistingSim = (usize, String, HashMap<String, ParamValue>);
+
+/// The parameters which differ between any of the existing sims.
+fn get_varying_params(existing_params: &[ExistingSim]) -> Vec<String> {
+    let first = match existing_params.first() {
+        Some((_, _, first)) => first,
+        None => return vec![],
+    };
+    existing_params
+        .iter()
+        .flat_map(|(_, _, params)| params.keys())
+        .unique()
+        .filter(|k| {
+            existing_params
+                .iter()
+                .any(|(_, _, params)| params.get(*k) != first.get(*k))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The parameters by which sims are identified: those which vary in
+/// either the new sim set or the existing sims.
+fn get_identity_params(sim_set: &SimSet, existing_varying_params: &[String]) -> Vec<String> {
+    sim_set
+        .iter()
+        .flat_map(|sim| sim.keys())
+        .filter(|k| sim_set.varies(k))
+        .chain(existing_varying_params.iter())
+        .unique()
+        .sorted()
+        .cloned()
+        .collect()
+}
+
+/// Returns the sims which do not exist yet. Fails if a sim exists but
+/// would be written with different parameters.
+fn find_new_sims<'a>(
+    sim_set: &'a SimSet,
+    existing_params: &[ExistingSim],
+    identity_params: &[String],
+) -> Result<Vec<&'a SimParams>> {
+    let mut conflicts = vec![];
+    let mut new_sims = vec![];
+    for (i, sim) in sim_set.enumerate() {
+        let existing = existing_params.iter().find(|(_, _, params)| {
+            identity_params
+                .iter()
+                .all(|param| params.get(param) == sim.get(param))
+        });
+        match existing {
+            None => new_sims.push(sim),
+            Some((_, name, params)) => {
+                let differing_params = get_differing_params(sim, params);
+                if !differing_params.is_empty() {
+                    conflicts.push(format!(
+                        "sim {} would change existing sim {} (parameters: {})",
+                        i,
+                        name,
+                        differing_params.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(anyhow!(
+            "Found conflicts with existing sims in output folder:\n{}",
+            conflicts.join("\n")
+        ));
+    }
+    Ok(new_sims)
+}
+
+/// The param file and config file parameters whose values differ.
+fn get_differing_params(sim: &SimParams, params: &HashMap<String, ParamValue>) -> Vec<String> {
+    sim.keys()
+        .chain(params.keys())
+        .unique()
+        .filter(|k| is_param_file_param(k) || is_config_file_param(k))
+        .filter(|k| sim.get(k) != params.get(*k))
+        .sorted()
+        .cloned()
+        .collect()
+}
+
+fn get_manifest(
+    sim_set: &SimSet,
+    input_folder: &Utf8Path,
+    source: &SimSetSource,
+) -> Result<SimSetManifest> {
+    let sims: Vec<ManifestEntry> = sim_set
+        .enumerate()
+        .map(|(i, sim)| {
+            let name = get_sim_folder_name(*i, sim)?;
+            Ok(ManifestEntry::new(*i, name, sim, sim_set))
+        })
+        .collect::<Result<_>>()?;
+    let mut names = HashSet::new();
+    for entry in sims.iter() {
+        if !names.insert(&entry.name) {
+            return Err(anyhow!(
+                "Folder name {} is used by more than one sim",
+                entry.name
+            ));
+        }
+    }
+    SimSetManifest::new(input_folder, source, sims)
+}
+
+fn get_sim_folder_name(index: usize, sim: &SimParams) -> Result<String> {
+    let template = match sim.get(config::FOLDER_NAME_IDENTIFIER) {
+        Some(template) => template.unwrap_string(),
+        None => return Ok(index.to_string()),
+    };
+    let mut replacements: HashMap<String, String> = sim
+        .iter()
+        .map(|(k, v)| (k.clone(), v.to_string()))
+        .collect();
+    replacements.insert("index".into(), index.to_string());
+    let name = strfmt_anyhow(template, replacements)
+        .with_context(|| format!("While formatting folder name template {}", template))?;
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return Err(anyhow!("Invalid sim folder name: {:?}", name));
+    }
+    Ok(name)
 }
 
 fn copy_sim(
@@ -53,8 +299,137 @@ fn copy_sim(
     sim.copy_treecol_file_if_exists(input_folder, sim_output_folder)?;
     sim.copy_output_list_file_if_exists(input_folder, sim_output_folder)?;
     sim.write_bob_param_file(&sim_output_folder.join(config::DEFAULT_BOB_PARAM_FILE_NAME))?;
+    write_sim_hash(sim_output_folder, &get_sim_hash(sim)?)?;
     // This is not the most efficient thing ever but it should be completely fine since this is not done very often, nor is the hashmap very large.
     let mut new_sim_params = sim.clone();
     new_sim_params.folder = sim_output_folder.to_owned();
     Ok(new_sim_params)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sim_params_with;
+
+    fn sim(params: &[(&str, ParamValue)]) -> SimParams {
+        let params: Vec<_> = [("numCores", ParamValue::Int(4))]
+            .into_iter()
+            .chain(params.iter().cloned())
+            .collect();
+        sim_params_with(&params)
+    }
+
+    fn existing(sims: &[SimParams]) -> Vec<ExistingSim> {
+        sims.iter()
+            .enumerate()
+            .map(|(i, sim)| {
+                let params = sim.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                (i, i.to_string(), params)
+            })
+            .collect()
+    }
+
+    fn new_sims(sim_set: &SimSet, existing_sims: &[ExistingSim]) -> Result<Vec<i64>> {
+        let identity_params = get_identity_params(sim_set, &get_varying_params(existing_sims));
+        Ok(find_new_sims(sim_set, existing_sims, &identity_params)?
+            .iter()
+            .map(|sim| sim["TimeMax"].unwrap_i64())
+            .collect())
+    }
+
+    fn sim_set(values: &[i64]) -> SimSet {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, sim(&[("TimeMax", ParamValue::Int(*v))])))
+            .collect()
+    }
+
+    #[test]
+    fn extend_with_new_sims() -> Result<()> {
+        let existing_sims = existing(&[sim(&[("TimeMax", ParamValue::Int(1))])]);
+        assert_eq!(new_sims(&sim_set(&[1, 2, 3]), &existing_sims)?, [2, 3]);
+        // A single new sim is only identified with an existing one if the varying params match
+        let existing_sims = existing(&[
+            sim(&[("TimeMax", ParamValue::Int(1))]),
+            sim(&[("TimeMax", ParamValue::Int(2))]),
+        ]);
+        assert_eq!(new_sims(&sim_set(&[3]), &existing_sims)?, [3]);
+        Ok(())
+    }
+
+    #[test]
+    fn extend_without_new_sims() -> Result<()> {
+        let existing_sims = existing(&[
+            sim(&[("TimeMax", ParamValue::Int(1))]),
+            sim(&[("TimeMax", ParamValue::Int(2))]),
+        ]);
+        assert_eq!(new_sims(&sim_set(&[2]), &existing_sims)?, Vec::<i64>::new());
+        assert_eq!(
+            new_sims(&sim_set(&[1, 2]), &existing_sims)?,
+            Vec::<i64>::new()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extend_with_list_param() -> Result<()> {
+        let rates = ParamValue::new(&serde_yaml::from_str("[1, 2]")?)?;
+        // The existing params are read from bobParams.yaml, which keeps the list typed
+        let mut existing_sims = existing(&[sim(&[
+            ("TimeMax", ParamValue::Int(1)),
+            ("TimeBegin", rates.clone()),
+        ])]);
+        existing_sims[0].2 = serde_yaml::from_str(&serde_yaml::to_string(&existing_sims[0].2)?)?;
+        let new_set = |time_begin: &ParamValue| -> SimSet {
+            [1, 2]
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    (
+                        i,
+                        sim(&[
+                            ("TimeMax", ParamValue::Int(*v)),
+                            ("TimeBegin", time_begin.clone()),
+                        ]),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(new_sims(&new_set(&rates), &existing_sims)?, [2]);
+        assert!(new_sims(&new_set(&ParamValue::Str("1,2".into())), &existing_sims).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn extend_with_conflict() {
+        let existing_sims = existing(&[
+            sim(&[
+                ("TimeMax", ParamValue::Int(1)),
+                ("TimeBegin", ParamValue::Int(0)),
+            ]),
+            sim(&[
+                ("TimeMax", ParamValue::Int(2)),
+                ("TimeBegin", ParamValue::Int(0)),
+            ]),
+        ]);
+        let new_set: SimSet = [1, 2]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                (
+                    i,
+                    sim(&[
+                        ("TimeMax", ParamValue::Int(*v)),
+                        ("TimeBegin", ParamValue::Int(1)),
+                        ("numCores", ParamValue::Int(8)),
+                    ]),
+                )
+            })
+            .collect();
+        let error = new_sims(&new_set, &existing_sims).unwrap_err().to_string();
+        assert!(error.contains("TimeBegin"));
+        // Special params such as numCores do not change the output
+        assert!(!error.contains("numCores"));
+    }
+}
@@ -25,6 +25,17 @@ pub fn copy_abundances(
     let coordinates_snap_path = coordinates_sim_path.join(&coordinates_sim.get_ics_filename());
     let coordinates_snap = Snapshot::from_file(&coordinates_sim, &coordinates_snap_path)?;
     let abundances_snap = last(get_snapshots(&abundances_sim)?).unwrap()?;
+    write_remapped_snapshot(abundances_snap, coordinates_snap, snap_output)
+}
+
+/// Writes a copy of `coordinates_snap` to `snap_output`, in which the abundances and
+/// energies are replaced by those of the nearest cell in `abundances_snap`.
+pub fn write_remapped_snapshot(
+    abundances_snap: Snapshot,
+    coordinates_snap: Snapshot,
+    snap_output: &Utf8Path,
+) -> Result<()> {
+    let coordinates_snap_path = coordinates_snap.path.clone();
     let (result_abundances, result_energies) =
         get_remapped_abundances_and_energies(abundances_snap, coordinates_snap)?;
     copy_file(coordinates_snap_path, snap_output)?;
@@ -56,29 +67,42 @@ fn get_remapped_abundances_and_energies<'a>(
     abundances_snap: Snapshot<'a>,
     coordinates_snap: Snapshot<'a>,
 ) -> Result<(FArray2, FArray1)> {
-    let reference_coords = abundances_snap.coordinates()?;
+    remap_to_nearest(
+        &abundances_snap.coordinates()?,
+        &abundances_snap.chemical_abundances()?,
+        &abundances_snap.energies()?,
+        &coordinates_snap.coordinates()?,
+        coordinates_snap.energies()?,
+    )
+}
+
+/// Gives each cell the abundances of the nearest reference cell and the larger
+/// of its own energy and that of the nearest reference cell.
+fn remap_to_nearest(
+    reference_coords: &FArray2,
+    reference_abundances: &FArray2,
+    reference_energies: &FArray1,
+    coords: &FArray2,
+    mut energies: FArray1,
+) -> Result<(FArray2, FArray1)> {
     let mut tree = KdTree::new(3);
     let reference_coords_iter = reference_coords.outer_iter().map(|x| [x[0], x[1], x[2]]);
     for (i, coord) in reference_coords_iter.enumerate() {
         tree.add(coord, i)?;
     }
-    let coords = coordinates_snap.coordinates()?;
     let shape = coords.shape();
     let coords_iter = coords.outer_iter().map(|x| [x[0], x[1], x[2]]);
-    let reference_abundances = abundances_snap.chemical_abundances()?;
-    let reference_energies = abundances_snap.energies()?;
-    let mut result_energies = coordinates_snap.energies()?;
-    let mut result_abundances = Array::zeros((shape[0], SWEEP_NFREQ));
+    let mut abundances = Array::zeros((shape[0], SWEEP_NFREQ));
     for (i, pos) in coords_iter.enumerate() {
         let (_, index) = tree.nearest(&pos, 1, &squared_euclidean).unwrap()[0];
         for j in 0..SWEEP_NFREQ {
-            result_abundances[[i, j]] = reference_abundances[[*index, j]];
+            abundances[[i, j]] = reference_abundances[[*index, j]];
         }
-        if reference_energies[[*index]] > result_energies[[i]] {
-            result_energies[[i]] = reference_energies[[*index]];
+        if reference_energies[[*index]] > energies[[i]] {
+            energies[[i]] = reference_energies[[*index]];
         }
     }
-    Ok((result_abundances, result_energies))
+    Ok((abundances, energies))
 }
 
 fn last<T>(iter: impl Iterator<Item = T>) -> Option<T> {
@@ -88,3 +112,29 @@ fn last<T>(iter: impl Iterator<Item = T>) -> Option<T> {
     }
     return result;
 }
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn remap_takes_nearest_cell() -> Result<()> {
+        let reference_coords = array![[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]];
+        let reference_abundances = Array::from_shape_fn((2, SWEEP_NFREQ), |(i, _)| i as f64);
+        let reference_energies = array![5.0, 1.0];
+        let coords = array![[0.9, 0.9, 0.9], [0.1, 0.0, 0.2], [0.8, 1.0, 1.0]];
+        let energies = array![2.0, 2.0, 0.5];
+        let (abundances, energies) = remap_to_nearest(
+            &reference_coords,
+            &reference_abundances,
+            &reference_energies,
+            &coords,
+            energies,
+        )?;
+        assert_eq!(abundances.column(0), array![1.0, 0.0, 1.0]);
+        assert_eq!(energies, array![2.0, 5.0, 1.0]);
+        Ok(())
+    }
+}
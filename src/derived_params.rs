@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::expression::Expr;
+use crate::param_value::ParamValue;
+use crate::sim_params::SimParams;
+
+/// Parameters whose values are computed from other parameters of the same sim,
+/// given in the `derived` section of the bob file.
+#[derive(Debug, Clone, Default)]
+pub struct DerivedParams {
+    exprs: HashMap<String, Expr>,
+}
+
+impl DerivedParams {
+    pub fn new(exprs: &HashMap<String, String>) -> Result<DerivedParams> {
+        let exprs = exprs
+            .iter()
+            .map(|(name, expr)| {
+                let expr = expr.parse().with_context(|| {
+                    format!("While parsing expression for derived parameter {}", name)
+                })?;
+                Ok((name.clone(), expr))
+            })
+            .collect::<Result<_>>()?;
+        Ok(DerivedParams { exprs })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.exprs.contains_key(name)
+    }
+
+    /// Evaluates all derived parameters for the given sim.
+    pub fn evaluate(&self, sim: &SimParams) -> Result<HashMap<String, ParamValue>> {
+        let mut values = HashMap::new();
+        for name in self.exprs.keys().sorted() {
+            self.evaluate_param(name, sim, &mut values, &mut vec![])?;
+        }
+        Ok(values)
+    }
+
+    fn evaluate_param(
+        &self,
+        name: &str,
+        sim: &SimParams,
+        values: &mut HashMap<String, ParamValue>,
+        stack: &mut Vec<String>,
+    ) -> Result<ParamValue> {
+        if let Some(value) = values.get(name) {
+            return Ok(value.clone());
+        }
+        if stack.iter().any(|param| param == name) {
+            return Err(anyhow!(
+                "Cyclic dependency between derived parameters: {} -> {}",
+                stack.join(" -> "),
+                name
+            ));
+        }
+        stack.push(name.to_owned());
+        let value = self.exprs[name]
+            .eval(&mut |param| {
+                if self.contains(param) {
+                    self.evaluate_param(param, sim, values, stack)
+                } else {
+                    sim.get(param).cloned().ok_or_else(|| {
+                        anyhow!(
+                            "Unknown parameter {} in expression for derived parameter {}",
+                            param,
+                            name
+                        )
+                    })
+                }
+            })
+            .with_context(|| format!("While evaluating derived parameter {}", name))?;
+        stack.pop();
+        values.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sim_params_with;
+
+    fn sim() -> SimParams {
+        sim_params_with(&[("BoxSize", ParamValue::new_float(2.0))])
+    }
+
+    fn derived(exprs: &[(&str, &str)]) -> Result<DerivedParams> {
+        DerivedParams::new(
+            &exprs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn derived_params_depending_on_each_other() -> Result<()> {
+        let derived = derived(&[
+            ("TestSourcePosX", "BoxSize / 2"),
+            ("TestSourcePosY", "TestSourcePosX * 3"),
+        ])?;
+        let values = derived.evaluate(&sim())?;
+        assert_eq!(values["TestSourcePosX"], ParamValue::new_float(1.0));
+        assert_eq!(values["TestSourcePosY"], ParamValue::new_float(3.0));
+        Ok(())
+    }
+
+    #[test]
+    fn cyclic_and_unknown_params() -> Result<()> {
+        let cyclic = derived(&[("a", "b + 1"), ("b", "a + 1")])?;
+        assert!(cyclic.evaluate(&sim()).is_err());
+        let unknown = derived(&[("a", "NotAParameter + 1")])?;
+        assert!(unknown.evaluate(&sim()).is_err());
+        Ok(())
+    }
+}
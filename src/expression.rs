@@ -0,0 +1,542 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use crate::param_value::ParamValue;
+
+/// A small expression language over simulation parameters, such as
+/// `TimeMax * 1e-3`, `"ics_" + ResolutionLevel` or `numCores >= 64 && SWEEP`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(ParamValue),
+    Param(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl std::fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Pow => "^",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Expr> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(anyhow!("Unexpected token {:?} in expression: {}", token, s)),
+        }
+    }
+}
+
+impl Expr {
+    /// The names of all parameters that appear in the expression.
+    pub fn params(&self) -> Vec<&str> {
+        match self {
+            Expr::Literal(_) => vec![],
+            Expr::Param(name) => vec![name],
+            Expr::Neg(expr) | Expr::Not(expr) => expr.params(),
+            Expr::Binary(_, lhs, rhs) => lhs.params().into_iter().chain(rhs.params()).collect(),
+            Expr::Call(_, args) => args.iter().flat_map(|arg| arg.params()).collect(),
+        }
+    }
+
+    pub fn eval(&self, lookup: &mut dyn FnMut(&str) -> Result<ParamValue>) -> Result<ParamValue> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Param(name) => lookup(name).map(ParamValue::without_unit),
+            Expr::Neg(expr) => match expr.eval(lookup)? {
+                ParamValue::Int(x) => Ok(ParamValue::Int(-x)),
+                ParamValue::Float(x, _) => Ok(ParamValue::new_float(-*x)),
+                value => Err(anyhow!("Cannot negate non-numeric value {}", value)),
+            },
+            Expr::Not(expr) => Ok(ParamValue::Bool(!expr.eval_bool(lookup)?)),
+            Expr::Binary(BinaryOp::And, lhs, rhs) => Ok(ParamValue::Bool(
+                lhs.eval_bool(lookup)? && rhs.eval_bool(lookup)?,
+            )),
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => Ok(ParamValue::Bool(
+                lhs.eval_bool(lookup)? || rhs.eval_bool(lookup)?,
+            )),
+            Expr::Binary(op, lhs, rhs) => {
+                apply_binary_op(*op, lhs.eval(lookup)?, rhs.eval(lookup)?)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(lookup))
+                    .collect::<Result<Vec<_>>>()?;
+                call_function(name, &args)
+            }
+        }
+    }
+
+    pub fn eval_bool(&self, lookup: &mut dyn FnMut(&str) -> Result<ParamValue>) -> Result<bool> {
+        match self.eval(lookup)? {
+            ParamValue::Bool(value) => Ok(value),
+            value => Err(anyhow!("Expected boolean value, found {}", value)),
+        }
+    }
+}
+
+fn compare(op: BinaryOp, lhs: &ParamValue, rhs: &ParamValue) -> Result<ParamValue> {
+    let ordering = match (lhs, rhs) {
+        (
+            ParamValue::Int(_) | ParamValue::Float(_, _),
+            ParamValue::Int(_) | ParamValue::Float(_, _),
+        ) => lhs.unwrap_f64().partial_cmp(&rhs.unwrap_f64()),
+        (ParamValue::Str(x), ParamValue::Str(y)) => Some(x.cmp(y)),
+        (ParamValue::Bool(x), ParamValue::Bool(y)) => Some(x.cmp(y)),
+        (ParamValue::None, ParamValue::None) => Some(Ordering::Equal),
+        _ => None,
+    };
+    let result = match (op, ordering) {
+        (BinaryOp::Eq, ordering) => ordering == Some(Ordering::Equal),
+        (BinaryOp::Ne, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => return Err(anyhow!("Cannot compare {} and {}", lhs, rhs)),
+        (BinaryOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+        (BinaryOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+        (BinaryOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+        (BinaryOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+        _ => unreachable!(),
+    };
+    Ok(ParamValue::Bool(result))
+}
+
+fn apply_binary_op(op: BinaryOp, lhs: ParamValue, rhs: ParamValue) -> Result<ParamValue> {
+    match (op, &lhs, &rhs) {
+        (
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge,
+            _,
+            _,
+        ) => compare(op, &lhs, &rhs),
+        (BinaryOp::Add, ParamValue::Str(_), _) | (BinaryOp::Add, _, ParamValue::Str(_)) => {
+            Ok(ParamValue::Str(format!("{}{}", lhs, rhs)))
+        }
+        (_, ParamValue::Int(x), ParamValue::Int(y)) if op != BinaryOp::Div => {
+            let result = match op {
+                BinaryOp::Add => x.checked_add(*y),
+                BinaryOp::Sub => x.checked_sub(*y),
+                BinaryOp::Mul => x.checked_mul(*y),
+                BinaryOp::Pow => u32::try_from(*y).ok().and_then(|y| x.checked_pow(y)),
+                _ => unreachable!(),
+            };
+            match result {
+                Some(result) => Ok(ParamValue::Int(result)),
+                None => apply_float_op(op, *x as f64, *y as f64),
+            }
+        }
+        (
+            _,
+            ParamValue::Int(_) | ParamValue::Float(_, _),
+            ParamValue::Int(_) | ParamValue::Float(_, _),
+        ) => apply_float_op(op, lhs.unwrap_f64(), rhs.unwrap_f64()),
+        _ => Err(anyhow!("Invalid operands for {}: {} and {}", op, lhs, rhs)),
+    }
+}
+
+fn apply_float_op(op: BinaryOp, x: f64, y: f64) -> Result<ParamValue> {
+    let result = match op {
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::Mul => x * y,
+        BinaryOp::Div => {
+            if y == 0.0 {
+                return Err(anyhow!("Division by zero"));
+            }
+            x / y
+        }
+        BinaryOp::Pow => x.powf(y),
+        _ => unreachable!(),
+    };
+    Ok(ParamValue::new_float(result))
+}
+
+fn call_function(name: &str, args: &[ParamValue]) -> Result<ParamValue> {
+    let float_arg = |i: usize| -> Result<f64> {
+        match &args[i] {
+            ParamValue::Int(_) | ParamValue::Float(_, _) => Ok(args[i].unwrap_f64()),
+            value => Err(anyhow!(
+                "Non-numeric argument {} to function {}",
+                value,
+                name
+            )),
+        }
+    };
+    let num_args = match name {
+        "min" | "max" | "pow" => 2,
+        "abs" | "sqrt" | "log10" | "exp" | "int" | "float" | "str" => 1,
+        _ => return Err(anyhow!("Unknown function in expression: {}", name)),
+    };
+    if args.len() != num_args {
+        return Err(anyhow!(
+            "Function {} takes {} arguments but {} were given",
+            name,
+            num_args,
+            args.len()
+        ));
+    }
+    let keep_int = args.iter().all(|arg| matches!(arg, ParamValue::Int(_)));
+    match name {
+        "min" | "max" if keep_int => {
+            let (x, y) = (args[0].unwrap_i64(), args[1].unwrap_i64());
+            Ok(ParamValue::Int(if name == "min" {
+                x.min(y)
+            } else {
+                x.max(y)
+            }))
+        }
+        "min" => Ok(ParamValue::new_float(float_arg(0)?.min(float_arg(1)?))),
+        "max" => Ok(ParamValue::new_float(float_arg(0)?.max(float_arg(1)?))),
+        "pow" => apply_binary_op(BinaryOp::Pow, args[0].clone(), args[1].clone()),
+        "abs" if keep_int => Ok(ParamValue::Int(args[0].unwrap_i64().abs())),
+        "abs" => Ok(ParamValue::new_float(float_arg(0)?.abs())),
+        "sqrt" => Ok(ParamValue::new_float(float_arg(0)?.sqrt())),
+        "log10" => Ok(ParamValue::new_float(float_arg(0)?.log10())),
+        "exp" => Ok(ParamValue::new_float(float_arg(0)?.exp())),
+        "int" => Ok(ParamValue::Int(float_arg(0)?.round() as i64)),
+        "float" => Ok(ParamValue::new_float(float_arg(0)?)),
+        "str" => Ok(ParamValue::Str(args[0].to_string())),
+        _ => unreachable!(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Str(String),
+    Ident(String),
+    Op(BinaryOp),
+    Not,
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut chars = s.chars().peekable();
+    let mut tokens = vec![];
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '+' => Token::Op(BinaryOp::Add),
+            '-' => Token::Op(BinaryOp::Sub),
+            '*' => Token::Op(BinaryOp::Mul),
+            '/' => Token::Op(BinaryOp::Div),
+            '^' => Token::Op(BinaryOp::Pow),
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Op(BinaryOp::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(BinaryOp::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(BinaryOp::Le),
+            '<' => Token::Op(BinaryOp::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(BinaryOp::Ge),
+            '>' => Token::Op(BinaryOp::Gt),
+            '&' if chars.next_if_eq(&'&').is_some() => Token::Op(BinaryOp::And),
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Op(BinaryOp::Or),
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '"' | '\'' => Token::Str(read_string(c, &mut chars)?),
+            c if c.is_ascii_digit() || c == '.' => Token::Number(read_number(c, &mut chars)),
+            c if c.is_alphabetic() || c == '_' => Token::Ident(read_while(c, &mut chars, |c| {
+                c.is_alphanumeric() || c == '_'
+            })),
+            c => return Err(anyhow!("Unexpected character '{}' in expression: {}", c, s)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn read_while(first: char, chars: &mut Peekable<Chars>, f: impl Fn(char) -> bool) -> String {
+    let mut result = first.to_string();
+    while let Some(c) = chars.peek().filter(|c| f(**c)) {
+        result.push(*c);
+        chars.next();
+    }
+    result
+}
+
+fn read_number(first: char, chars: &mut Peekable<Chars>) -> String {
+    let mut result = read_while(first, chars, |c| c.is_ascii_digit() || c == '.');
+    if let Some(e) = chars.peek().filter(|c| **c == 'e' || **c == 'E').copied() {
+        chars.next();
+        result.push(e);
+        if let Some(sign) = chars.peek().filter(|c| **c == '+' || **c == '-').copied() {
+            chars.next();
+            result.push(sign);
+        }
+        while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+            result.push(*c);
+            chars.next();
+        }
+    }
+    result
+}
+
+fn read_string(quote: char, chars: &mut Peekable<Chars>) -> Result<String> {
+    let mut result = String::new();
+    for c in chars.by_ref() {
+        if c == quote {
+            return Ok(result);
+        }
+        result.push(c);
+    }
+    Err(anyhow!(
+        "Unterminated string in expression: {}{}",
+        quote,
+        result
+    ))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(anyhow!("Expected {:?}, found {:?}", expected, token)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[BinaryOp],
+        mut operand: impl FnMut(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut lhs = operand(self)?;
+        while let Some(Token::Op(op)) = self.peek().cloned() {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.next();
+            let rhs = operand(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(&[BinaryOp::Or], Self::conjunction)
+    }
+
+    fn conjunction(&mut self) -> Result<Expr> {
+        self.binary(&[BinaryOp::And], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let lhs = self.sum()?;
+        match self.peek().cloned() {
+            Some(Token::Op(
+                op @ (BinaryOp::Eq
+                | BinaryOp::Ne
+                | BinaryOp::Lt
+                | BinaryOp::Le
+                | BinaryOp::Gt
+                | BinaryOp::Ge),
+            )) => {
+                self.next();
+                let rhs = self.sum()?;
+                Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+            }
+            _ => Ok(lhs),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        self.binary(&[BinaryOp::Add, BinaryOp::Sub], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        self.binary(&[BinaryOp::Mul, BinaryOp::Div], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Op(BinaryOp::Sub)) {
+            self.next();
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.atom()?;
+        if self.peek() == Some(&Token::Op(BinaryOp::Pow)) {
+            self.next();
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(s)) => match ParamValue::from_str(&s)? {
+                value @ (ParamValue::Int(_) | ParamValue::Float(_, _)) => Ok(Expr::Literal(value)),
+                _ => Err(anyhow!("Invalid number in expression: {}", s)),
+            },
+            Some(Token::Str(s)) => Ok(Expr::Literal(ParamValue::Str(s))),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(ParamValue::Bool(true))),
+                "false" => Ok(Expr::Literal(ParamValue::Bool(false))),
+                _ if self.peek() == Some(&Token::LParen) => {
+                    self.next();
+                    let mut args = vec![];
+                    if self.peek() != Some(&Token::RParen) {
+                        args.push(self.expr()?);
+                        while self.peek() == Some(&Token::Comma) {
+                            self.next();
+                            args.push(self.expr()?);
+                        }
+                    }
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::Call(name, args))
+                }
+                _ => Ok(Expr::Param(name)),
+            },
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            token => Err(anyhow!("Unexpected token in expression: {:?}", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn eval(expr: &str, params: &[(&str, ParamValue)]) -> Result<ParamValue> {
+        let params: HashMap<&str, ParamValue> = params.iter().cloned().collect();
+        expr.parse::<Expr>()?.eval(&mut |name| {
+            params
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown parameter {}", name))
+        })
+    }
+
+    #[test]
+    fn arithmetic() -> Result<()> {
+        let params = [
+            ("TimeMax", ParamValue::new_float(0.5)),
+            ("BoxSize", ParamValue::Int(2)),
+        ];
+        assert_eq!(
+            eval("TimeMax * 1e-3", &params)?,
+            ParamValue::new_float(0.0005)
+        );
+        assert_eq!(eval("BoxSize / 2", &params)?, ParamValue::new_float(1.0));
+        assert_eq!(eval("-BoxSize + 3 * 2", &params)?, ParamValue::Int(4));
+        assert_eq!(eval("2 ^ 3 ^ 2", &params)?, ParamValue::Int(512));
+        assert_eq!(eval("(1 + 2) * 3", &params)?, ParamValue::Int(9));
+        assert_eq!(eval("max(BoxSize, 5)", &params)?, ParamValue::Int(5));
+        assert!(eval("1 / 0", &params).is_err());
+        assert!(eval("Unknown + 1", &params).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn strings() -> Result<()> {
+        let params = [("ResolutionLevel", ParamValue::Int(7))];
+        assert_eq!(
+            eval("'ics_res' + ResolutionLevel", &params)?,
+            ParamValue::Str("ics_res7".into())
+        );
+        assert!(eval("'a' * 2", &params).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn predicates() -> Result<()> {
+        let params = [
+            ("numCores", ParamValue::Int(64)),
+            ("SWEEP", ParamValue::Bool(true)),
+            ("simType", ParamValue::Str("sweep".into())),
+        ];
+        let yes = ParamValue::Bool(true);
+        let no = ParamValue::Bool(false);
+        assert_eq!(eval("numCores >= 64 && SWEEP == true", &params)?, yes);
+        assert_eq!(eval("numCores > 64 || !SWEEP", &params)?, no);
+        assert_eq!(eval("numCores == 64.0", &params)?, yes);
+        assert_eq!(eval("simType != 'sweep'", &params)?, no);
+        assert_eq!(eval("1 + 2 * 3 == 7 && 2 < 3", &params)?, yes);
+        assert!(eval("simType < 3", &params).is_err());
+        assert!(eval("numCores && SWEEP", &params).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_syntax() {
+        assert!("1 +".parse::<Expr>().is_err());
+        assert!("(1 + 2".parse::<Expr>().is_err());
+        assert!("1 2".parse::<Expr>().is_err());
+        assert!("'abc".parse::<Expr>().is_err());
+    }
+}
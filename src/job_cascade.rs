@@ -1,14 +1,20 @@
 use std::collections::HashMap;
 
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use camino::Utf8Path;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_yaml::Value;
+use uom::si::f64::Time;
+use uom::si::time::second;
+use uom::si::time::year;
 
 use crate::config::CASCADE_IDENTIFIER;
 use crate::config::CONFIG_FILE;
+use crate::config::ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER;
+use crate::config::PREVIOUS_SEGMENT_ICS_FILE_NAME;
 use crate::param_value::ParamValue;
 use crate::postprocess::read_hdf5::read_attr_f64;
 use crate::sim_params::SimParams;
@@ -21,12 +27,26 @@ pub enum ArepoTime {
 }
 
 impl ArepoTime {
+    fn value(&self) -> f64 {
+        match self {
+            Self::Time(t) => *t,
+            Self::ScaleFactor(a) => *a,
+        }
+    }
+
+    fn with_value(&self, value: f64) -> ArepoTime {
+        match self {
+            Self::Time(_) => Self::Time(value),
+            Self::ScaleFactor(_) => Self::ScaleFactor(value),
+        }
+    }
+
     /// Returns the time in code units between `self` and `other`.
     /// Assumes that all times are given in the same code units.
-    fn time_until(&self, other: &ArepoTime, sim: &SimParams) -> f64 {
+    fn time_until(&self, other: &ArepoTime, sim: &SimParams) -> Result<f64> {
         match self {
             Self::Time(t1) => match other {
-                ArepoTime::Time(t2) => t2 - t1,
+                ArepoTime::Time(t2) => Ok(t2 - t1),
                 ArepoTime::ScaleFactor(_) => unreachable!(),
             },
             Self::ScaleFactor(a1) => match other {
@@ -37,52 +57,207 @@ impl ArepoTime {
     }
 }
 
-fn get_time_between_scale_factors(a1: f64, a2: f64, sim: &SimParams) -> f64 {
-    let omega_lambda = sim["OmegaLambda"].unwrap_f64();
-    let omega_0 = sim["Omega0"].unwrap_f64();
-    let hubble_param = sim["HubbleParam"].unwrap_f64();
-    let hubble = 3.2407789e-18;
-    let a_to_t = |val: f64| {
-        let factor1 = 2.0 / (3.0 * omega_lambda.sqrt());
-        let term1 = (omega_lambda / omega_0).sqrt() * val.powf(1.5);
-        let term2 = (1.0 + omega_lambda / omega_0 * val.powi(3)).sqrt();
+/// A time as given in the bob file, which is resolved to an `ArepoTime`
+/// using the units and cosmology of the sim.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum CascadeTime {
+    Time(f64),
+    ScaleFactor(f64),
+    #[serde(rename = "kyr")]
+    Kyr(f64),
+    #[serde(rename = "myr")]
+    Myr(f64),
+    #[serde(rename = "redshift")]
+    Redshift(f64),
+}
+
+impl CascadeTime {
+    /// In cosmological sims, times given in years are taken as the age of the universe
+    /// and converted to scale factors, so that they can be combined with redshifts.
+    pub fn resolve(&self, sim: &SimParams) -> Result<ArepoTime> {
+        let years_to_code_units = |years: f64| (Time::new::<year>(years) / sim.units.time).value;
+        let years_to_arepo_time = |years: f64| -> Result<ArepoTime> {
+            match is_comoving(sim) {
+                true => Ok(ArepoTime::ScaleFactor(
+                    Cosmology::from_sim(sim)?
+                        .scale_factor_at_age(Time::new::<year>(years).get::<second>()),
+                )),
+                false => Ok(ArepoTime::Time(years_to_code_units(years))),
+            }
+        };
+        match self {
+            Self::Time(t) => Ok(ArepoTime::Time(*t)),
+            Self::ScaleFactor(a) => Ok(ArepoTime::ScaleFactor(*a)),
+            Self::Kyr(t) => years_to_arepo_time(t * 1e3),
+            Self::Myr(t) => years_to_arepo_time(t * 1e6),
+            Self::Redshift(z) => Ok(ArepoTime::ScaleFactor(1.0 / (1.0 + z))),
+        }
+    }
+}
+
+fn is_comoving(sim: &SimParams) -> bool {
+    matches!(
+        sim.get("ComovingIntegrationOn"),
+        Some(ParamValue::Int(1)) | Some(ParamValue::Bool(true))
+    )
+}
+
+/// A flat cosmology with matter and a cosmological constant.
+struct Cosmology {
+    omega_lambda: f64,
+    omega_0: f64,
+    hubble_param: f64,
+}
+
+impl Cosmology {
+    fn from_sim(sim: &SimParams) -> Result<Cosmology> {
+        let get = |param: &str| {
+            sim.get(param)
+                .map(|value| value.unwrap_f64())
+                .ok_or_else(|| anyhow!("Missing cosmological parameter {}", param))
+        };
+        Ok(Cosmology {
+            omega_lambda: get("OmegaLambda")?,
+            omega_0: get("Omega0")?,
+            hubble_param: get("HubbleParam")?,
+        })
+    }
+
+    /// The Hubble time in seconds
+    fn hubble_time(&self) -> f64 {
+        let hubble = 3.2407789e-18;
+        1.0 / (self.hubble_param * hubble)
+    }
+
+    /// The age of the universe in seconds at the scale factor.
+    fn age_at_scale_factor(&self, a: f64) -> f64 {
+        let factor1 = 2.0 / (3.0 * self.omega_lambda.sqrt());
+        let term1 = (self.omega_lambda / self.omega_0).sqrt() * a.powf(1.5);
+        let term2 = (1.0 + self.omega_lambda / self.omega_0 * a.powi(3)).sqrt();
         let factor2 = (term1 + term2).ln();
-        factor1 * factor2
-    };
-    let t1 = a_to_t(a1);
-    let t2 = a_to_t(a2);
-    let diff_h = t2 - t1;
-    let diff_secs = diff_h / (hubble_param * hubble);
+        factor1 * factor2 * self.hubble_time()
+    }
+
+    /// The inverse of `age_at_scale_factor`.
+    fn scale_factor_at_age(&self, age: f64) -> f64 {
+        let x = (1.5 * self.omega_lambda.sqrt() * age / self.hubble_time()).sinh();
+        (x * x * self.omega_0 / self.omega_lambda).cbrt()
+    }
+}
+
+fn get_time_between_scale_factors(a1: f64, a2: f64, sim: &SimParams) -> Result<f64> {
+    let cosmology = Cosmology::from_sim(sim)?;
+    let diff_secs = cosmology.age_at_scale_factor(a2) - cosmology.age_at_scale_factor(a1);
     let secs_in_code_units =
         sim["UnitLength_in_cm"].unwrap_f64() / sim["UnitVelocity_in_cm_per_s"].unwrap_f64();
-    diff_secs / secs_in_code_units
+    Ok(diff_secs / secs_in_code_units)
+}
+
+/// Determines where the segments of a cascade begin.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CascadeSegments {
+    /// Each segment starts from one of the given snapshots, at the time given in its header.
+    Snapshots {
+        files: Vec<String>,
+        original_simulation_comoving: bool,
+    },
+    /// The time between `start_time` and the final time is split into `num_segments` equal parts
+    /// (equal in the scale factor if the times are given as scale factors).
+    /// Later segments start from the last snapshot of the previous segment.
+    EqualSplits {
+        start_time: CascadeTime,
+        num_segments: usize,
+    },
+    /// Each segment starts at one of the given times.
+    Boundaries { boundary_times: Vec<CascadeTime> },
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CascadeArgs {
-    files: Vec<String>,
-    final_time: ArepoTime,
-    original_simulation_comoving: bool,
+    #[serde(flatten)]
+    segments: CascadeSegments,
+    final_time: CascadeTime,
 }
 
 impl CascadeArgs {
-    pub fn get_times(&self, folder: &Utf8Path) -> Vec<ArepoTime> {
-        let mut times = vec![];
-        for file in self.files.iter() {
-            times.push(self.get_time_for_snapshot(&folder.join(file)).unwrap());
+    pub fn num_segments(&self) -> usize {
+        match &self.segments {
+            CascadeSegments::Snapshots { files, .. } => files.len(),
+            CascadeSegments::EqualSplits { num_segments, .. } => *num_segments,
+            CascadeSegments::Boundaries { boundary_times } => boundary_times.len(),
         }
-        times.push(self.final_time);
-        times
     }
 
-    fn get_time_for_snapshot(&self, path: &Utf8Path) -> Result<ArepoTime> {
-        let h5file = hdf5::File::open_rw(path)?;
-        let val = read_attr_f64(&h5file, "Time")?;
-        if self.original_simulation_comoving {
-            Ok(ArepoTime::ScaleFactor(val))
-        } else {
-            Ok(ArepoTime::Time(val))
+    /// Returns the start times of all segments, followed by the final time.
+    pub fn get_times(&self, folder: &Utf8Path, sim: &SimParams) -> Result<Vec<ArepoTime>> {
+        let final_time = self.final_time.resolve(sim)?;
+        let mut times = match &self.segments {
+            CascadeSegments::Snapshots {
+                files,
+                original_simulation_comoving,
+            } => files
+                .iter()
+                .map(|file| {
+                    get_time_for_snapshot(&folder.join(file), *original_simulation_comoving)
+                })
+                .collect::<Result<Vec<_>>>()?,
+            CascadeSegments::EqualSplits {
+                start_time,
+                num_segments,
+            } => {
+                let start_time = start_time.resolve(sim)?;
+                check_same_kind(&start_time, &final_time)?;
+                let (start, end) = (start_time.value(), final_time.value());
+                (0..*num_segments)
+                    .map(|i| {
+                        start_time
+                            .with_value(start + (end - start) * i as f64 / *num_segments as f64)
+                    })
+                    .collect()
+            }
+            CascadeSegments::Boundaries { boundary_times } => boundary_times
+                .iter()
+                .map(|time| time.resolve(sim))
+                .collect::<Result<Vec<_>>>()?,
+        };
+        if times.is_empty() {
+            return Err(anyhow!("Cascade contains no segments"));
+        }
+        times.push(final_time);
+        for (time_begin, time_end) in times.iter().zip(times[1..].iter()) {
+            check_same_kind(time_begin, time_end)?;
+            if time_end.value() <= time_begin.value() {
+                return Err(anyhow!(
+                    "Cascade segment boundaries are not increasing: {:?} to {:?}",
+                    time_begin,
+                    time_end
+                ));
+            }
         }
+        Ok(times)
+    }
+}
+
+fn get_time_for_snapshot(path: &Utf8Path, original_simulation_comoving: bool) -> Result<ArepoTime> {
+    let h5file = hdf5::File::open_rw(path)?;
+    let val = read_attr_f64(&h5file, "Time")?;
+    if original_simulation_comoving {
+        Ok(ArepoTime::ScaleFactor(val))
+    } else {
+        Ok(ArepoTime::Time(val))
+    }
+}
+
+fn check_same_kind(time1: &ArepoTime, time2: &ArepoTime) -> Result<()> {
+    match (time1, time2) {
+        (ArepoTime::Time(_), ArepoTime::Time(_))
+        | (ArepoTime::ScaleFactor(_), ArepoTime::ScaleFactor(_)) => Ok(()),
+        _ => Err(anyhow!(
+            "Cannot mix times and scale factors in cascade: {:?} and {:?}",
+            time1,
+            time2
+        )),
     }
 }
 
@@ -96,9 +271,9 @@ pub fn get_substitutions_cascade(
     substitutions: &HashMap<String, Value>,
     cascade: &CascadeArgs,
 ) -> Result<Vec<HashMap<String, ParamValue>>> {
-    let times = cascade.get_times(folder);
+    let times = cascade.get_times(folder, base_sim_params)?;
     let mut other_substitutions =
-        get_non_cascade_substitutions(substitutions, cascade.files.len())?;
+        get_non_cascade_substitutions(substitutions, cascade.num_segments())?;
     let mut insert_substitution = |i, name: &str, value| {
         let result: &mut HashMap<_, _> = &mut other_substitutions[i];
         match result.insert(name.into(), value) {
@@ -109,15 +284,15 @@ pub fn get_substitutions_cascade(
             None => {}
         }
     };
-    assert_eq!(times.len(), cascade.files.len() + 1);
+    assert_eq!(times.len(), cascade.num_segments() + 1);
     for (i, (time_begin, time_end)) in times.iter().zip(times[1..].iter()).enumerate() {
-        let file = &cascade.files[i];
-        let time_diff = time_begin.time_until(time_end, base_sim_params);
+        let time_diff = time_begin
+            .time_until(time_end, base_sim_params)
+            .with_context(|| format!("While computing the length of segment {}", i))?;
         println!(
-            "sim {}: {:?} to {:?} ({:.5})",
+            "sim {}: {:?} to {:?} ({:.5} code units)",
             i, time_begin, time_end, time_diff
         );
-        insert_substitution(i, "InitCondFile", ParamValue::Str(strip_ending(file)));
         insert_substitution(i, "TimeBegin", ParamValue::new_float(0.0));
         insert_substitution(i, "TimeMax", ParamValue::new_float(time_diff));
         insert_substitution(
@@ -131,48 +306,66 @@ pub fn get_substitutions_cascade(
             ParamValue::new_float(time_diff * 1e-9),
         );
         insert_substitution(i, CASCADE_IDENTIFIER, ParamValue::Bool(true));
-        let rewrite_snapshot_command = get_command_to_rewrite_snapshot(i, file);
-        insert_substitution(
-            i,
-            "additionalCommands",
-            ParamValue::Str(rewrite_snapshot_command),
-        );
+        match &cascade.segments {
+            CascadeSegments::Snapshots { files, .. } => {
+                insert_substitution(i, "InitCondFile", ParamValue::Str(strip_ending(&files[i])));
+            }
+            // The first segment starts from the ICs of the base sim
+            _ if i == 0 => {}
+            _ => {
+                insert_substitution(
+                    i,
+                    "InitCondFile",
+                    ParamValue::Str(PREVIOUS_SEGMENT_ICS_FILE_NAME.into()),
+                );
+                insert_substitution(i, "ICFormat", ParamValue::Int(3));
+                insert_substitution(
+                    i,
+                    ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER,
+                    ParamValue::Bool(true),
+                );
+            }
+        };
+        let command = get_handoff_command(i)?;
+        insert_substitution(i, "additionalCommands", ParamValue::Str(command));
     }
     Ok(other_substitutions)
 }
 
-fn get_command_to_rewrite_snapshot(num: usize, snap_name: &str) -> String {
-    match num {
-        0 => "".into(),
-        num => {
-            format!(
-                "{bob_path} copy-abundances ../{num}/ . {rewritten_name}; rm {normal_name}; mv {rewritten_name} {normal_name}; ",
-                bob_path = CONFIG_FILE.bob_path,
-                num = num - 1,
-                rewritten_name = get_rewritten_snapshot_name(snap_name),
-                normal_name = snap_name,
-            )
-        }
+/// The job of each segment after the first prepares its ICs from the previous
+/// segment before starting and fails if that is not possible. This runs the
+/// bob executable at `bob_path` of the bob config, so it has to exist at the
+/// same path on the compute nodes. The job checks this before running it.
+fn get_handoff_command(num: usize) -> Result<String> {
+    if num == 0 {
+        return Ok("".into());
     }
-}
-
-fn get_rewritten_snapshot_name(original_snap_name: &str) -> String {
-    format!("{}_rewritten.hdf5", strip_ending(original_snap_name))
+    let bob_path = &CONFIG_FILE.bob_path;
+    if !bob_path.is_file() {
+        return Err(anyhow!(
+            "bob_path {} in the bob config does not exist, but the jobs of cascade segments run it",
+            bob_path
+        ));
+    }
+    Ok(format!(
+        "[ -x {bob_path} ] || {{ echo \"bob not found at {bob_path}, check bob_path in the bob config\" >&2; exit 1; }}; {bob_path} cascade-handoff . || exit 1; ",
+        bob_path = bob_path,
+    ))
 }
 
 fn get_non_cascade_substitutions(
     substitutions: &HashMap<String, Value>,
-    num_cascade_files: usize,
+    num_segments: usize,
 ) -> Result<Vec<HashMap<String, ParamValue>>> {
     let mut sims = get_substitutions_cartesian(substitutions, None)?;
     if sims.len() == 1 {
-        sims = repeat(&sims[0], num_cascade_files);
+        sims = repeat(&sims[0], num_segments);
         Ok(sims)
-    } else if sims.len() != num_cascade_files {
+    } else if sims.len() != num_segments {
         Err(anyhow!(
-            "Number of substitution sims and number of cascade files do not match: {} vs {}",
+            "Number of substitution sims and number of cascade segments do not match: {} vs {}",
             sims.len(),
-            num_cascade_files,
+            num_segments,
         ))
     } else {
         Ok(sims)
@@ -182,3 +375,110 @@ fn get_non_cascade_substitutions(
 fn repeat<T: Clone>(x: &T, num: usize) -> Vec<T> {
     (0..num).map(|_| x.clone()).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sim_params_with;
+
+    fn sim(comoving: bool) -> SimParams {
+        // A time unit of one million years
+        let mut params = vec![("UnitLength_in_cm", ParamValue::new_float(3.1536e13))];
+        if comoving {
+            params.extend([
+                ("ComovingIntegrationOn", ParamValue::Int(1)),
+                ("OmegaLambda", ParamValue::new_float(0.7)),
+                ("Omega0", ParamValue::new_float(0.3)),
+                ("HubbleParam", ParamValue::new_float(0.7)),
+            ]);
+        }
+        sim_params_with(&params)
+    }
+
+    fn get_times(args: &str, comoving: bool) -> Result<Vec<ArepoTime>> {
+        let args: CascadeArgs = serde_yaml::from_str(args)?;
+        args.get_times(Utf8Path::new("."), &sim(comoving))
+    }
+
+    fn get_time_values(args: &str) -> Result<Vec<f64>> {
+        Ok(get_times(args, false)?
+            .iter()
+            .map(|time| time.value())
+            .collect())
+    }
+
+    #[test]
+    fn equal_time_splits() -> Result<()> {
+        let times = get_time_values(
+            "
+start_time: {Time: 1.0}
+final_time: {myr: 4.0}
+num_segments: 3
+",
+        )?;
+        assert_eq!(times.len(), 4);
+        for (time, expected) in times.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((time - expected).abs() < 1e-10);
+        }
+        assert!(get_time_values(
+            "
+boundary_times: [{Time: 2.0}, {kyr: 1000.0}]
+final_time: {Time: 4.0}
+",
+        )
+        .is_err());
+        let times = get_time_values(
+            "
+boundary_times: [{redshift: 9.0}]
+final_time: {ScaleFactor: 0.2}
+",
+        )?;
+        assert_eq!(times, vec![0.1, 0.2]);
+        Ok(())
+    }
+
+    #[test]
+    fn non_comoving_years_are_code_unit_times() -> Result<()> {
+        let times = get_times(
+            "boundary_times: [{kyr: 500.0}]\nfinal_time: {myr: 2.0}\n",
+            false,
+        )?;
+        assert!(matches!(times[0], ArepoTime::Time(t) if (t - 0.5).abs() < 1e-10));
+        assert!(matches!(times[1], ArepoTime::Time(t) if (t - 2.0).abs() < 1e-10));
+        assert!(get_times(
+            "boundary_times: [{myr: 1.0}]\nfinal_time: {redshift: 0.0}\n",
+            false
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn scale_factors_without_cosmology_are_an_error() -> Result<()> {
+        let cascade: CascadeArgs = serde_yaml::from_str(
+            "boundary_times: [{redshift: 9.0}]\nfinal_time: {ScaleFactor: 0.2}\n",
+        )?;
+        let substitutions = serde_yaml::from_str("numCores: 4")?;
+        let result =
+            get_substitutions_cascade(&sim(false), Utf8Path::new("."), &substitutions, &cascade);
+        assert!(format!("{:?}", result.unwrap_err()).contains("Missing cosmological parameter"));
+        Ok(())
+    }
+
+    #[test]
+    fn comoving_years_are_ages() -> Result<()> {
+        let sim = sim(true);
+        // The age of the universe today is about 13.5 Gyr for these parameters
+        let today = CascadeTime::Myr(13470.0).resolve(&sim)?;
+        assert!(matches!(today, ArepoTime::ScaleFactor(a) if (a - 1.0).abs() < 1e-2));
+        let a = CascadeTime::Myr(500.0).resolve(&sim)?;
+        let age = ArepoTime::ScaleFactor(0.0).time_until(&a, &sim)?;
+        assert!((age - 500.0).abs() < 1e-6);
+        let times = get_times(
+            "start_time: {myr: 500.0}\nfinal_time: {redshift: 0.0}\nnum_segments: 2\n",
+            true,
+        )?;
+        assert!(matches!(times[2], ArepoTime::ScaleFactor(a) if a == 1.0));
+        Ok(())
+    }
+}
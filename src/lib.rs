@@ -1,24 +1,44 @@
+pub mod arepo_config_file;
 pub mod arepo_log_file;
+pub mod arepo_options;
 pub mod array_utils;
+pub mod bisect;
+pub mod bob_file_check;
+pub mod cascade_handoff;
 pub mod config;
 pub mod config_file;
 pub mod copy;
 pub mod copy_abundances;
+pub mod derived_params;
 pub mod diff;
+pub mod expression;
 pub mod get_data;
 pub mod job_cascade;
 pub mod job_params;
 pub mod make;
+pub mod option_rules;
+pub mod param_file;
+pub mod param_override;
 pub mod param_value;
 pub mod postprocess;
+pub mod quantity;
+pub mod range_generator;
 pub mod run;
+pub mod sampling;
+pub mod search;
+pub mod sim_hash;
 pub mod sim_params;
 pub mod sim_set;
+pub mod sim_set_manifest;
 pub mod sim_units;
 pub mod simplex_log_file;
 pub mod source_file;
 pub mod strfmt_utils;
+pub mod substitution_filter;
 pub mod systype;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod thread_pool;
 pub mod unit_utils;
 pub mod util;
+pub mod yaml_include;
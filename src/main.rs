@@ -4,19 +4,33 @@ use anyhow::anyhow;
 use anyhow::Result;
 use args::StartSimulation;
 use args::SubCommand;
+use bob::arepo_options::load_arepo_options;
+use bob::arepo_options::warn_about_unknown_params;
+use bob::bob_file_check::check_bob_file;
+use bob::bob_file_check::problems_to_result;
+use bob::cascade_handoff::cascade_handoff;
 use bob::config;
-use bob::config::DEFAULT_BOB_CONFIG_NAME;
 use bob::copy::copy_sim_set;
 use bob::copy_abundances::copy_abundances;
 use bob::diff;
 use bob::get_data::get_data;
 use bob::make::build_sim_set;
+use bob::option_rules::check_option_rules;
+use bob::param_override::ParamOverride;
 use bob::param_value::ParamValue;
 use bob::postprocess::plot::replot;
 use bob::postprocess::postprocess_sim_set;
 use bob::run::run_sim_set;
+use bob::search::search;
+use bob::sim_hash::find_existing_runs;
 use bob::sim_params::SimParams;
+use bob::sim_set::filter_sim_set;
+use bob::sim_set::find_bob_file;
+use bob::sim_set::get_sim_set_names;
 use bob::sim_set::SimSet;
+use bob::sim_set_manifest::SimSetManifest;
+use bob::sim_set_manifest::SimSetSource;
+use bob::substitution_filter::FilterReport;
 use bob::unit_utils::nice_time;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
@@ -28,39 +42,84 @@ pub mod args;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let a = Opts::parse();
+    load_arepo_options();
     match a.subcmd {
         SubCommand::Show(l) => {
-            let sim_set = get_sim_set_from_input(&l.folder)?;
+            let config_file_path = find_bob_file(&l.folder, l.set_file.as_deref())?;
+            let (sim_set, filter_reports) = SimSet::from_bob_file_and_input_folder_with_overrides(
+                &config_file_path,
+                &l.folder,
+                &l.overrides,
+                l.cartesian,
+            )?;
+            let sim_set = filter_sim_set(sim_set, None, l.condition.as_ref())?;
             show_sim_set(sim_set, &l.param_names, l.all)?;
+            show_filter_reports(&filter_reports);
+            show_sim_set_names(&l.folder)?;
+        }
+        SubCommand::Check(l) => {
+            let config_file_path = find_bob_file(&l.folder, l.set_file.as_deref())?;
+            let problems = check_bob_file(&config_file_path)?;
+            problems_to_result(&config_file_path, &problems)?;
+            let sim_set = get_sim_set_from_input(&l.folder, l.set_file.as_deref(), &[], false)?;
+            check_option_rules(&sim_set)?;
+            warn_about_unknown_params(&sim_set);
+            println!("No problems found in {}", config_file_path);
         }
         SubCommand::Diff(l) => {
             diff::show_sim_diff(&l.folder1, &l.folder2)?;
         }
         SubCommand::ShowOutput(l) => {
-            let sim_set = get_sim_set_from_output(&l.output_folder)?;
-            show_sim_set(sim_set, &l.param_names, l.all)?;
+            let manifest = SimSetManifest::read(&l.output_folder)?;
+            match manifest {
+                Some(manifest) if l.param_names.is_empty() && !l.all && l.condition.is_none() => {
+                    show_manifest(&manifest)
+                }
+                _ => {
+                    let sim_set = get_sim_set_from_output(&l.output_folder)?;
+                    let sim_set = filter_sim_set(sim_set, None, l.condition.as_ref())?;
+                    show_sim_set(sim_set, &l.param_names, l.all)?;
+                }
+            }
         }
         SubCommand::Copy(l) => {
-            let sim_set = get_sim_set_from_input(&l.input_folder)?;
+            let mut source = SimSetSource {
+                set_name: l.set_file.clone(),
+                overrides: l.overrides.clone(),
+                cartesian: l.cartesian,
+                ..Default::default()
+            };
+            let sim_set = SimSet::from_input_folder(&l.input_folder, &mut source)?;
             copy_sim_set(
                 &sim_set,
-                l.input_folder,
-                l.output_folder,
+                &l.input_folder,
+                &l.output_folder,
+                &source,
                 l.delete,
+                l.extend,
                 l.symlink_ics,
             )?;
         }
         SubCommand::Build(l) => {
             let sim_set = get_sim_set_from_output(&l.output_folder)?;
+            let sim_set = filter_sim_set(sim_set, None, l.condition.as_ref())?;
             build_sim_set(&sim_set, a.verbose, &l.systype)?;
         }
         SubCommand::Run(l) => {
             let sim_set = get_sim_set_from_output(&l.output_folder)?;
+            let sim_set = filter_sim_set(sim_set, None, l.condition.as_ref())?;
             run_sim_set(&sim_set, a.verbose)?;
         }
         SubCommand::Start(l) => {
-            let sim_set = get_sim_set_from_input(&l.input_folder)?;
-            start_sim_set(sim_set, &l, a.verbose)?;
+            start_sim_set(&l, a.verbose)?;
+        }
+        SubCommand::Search(l) => {
+            search(&l, a.verbose)?;
+        }
+        SubCommand::FindExisting(l) => {
+            let sim_set =
+                get_sim_set_from_input(&l.input_folder, l.set_file.as_deref(), &[], false)?;
+            show_existing_runs(&sim_set, &l.output_roots)?;
         }
         SubCommand::GetData(l) => {
             get_data(&l.source_folder, &l.target_folder)?;
@@ -79,16 +138,28 @@ fn main() -> Result<(), Box<dyn Error>> {
         SubCommand::CopyAbundances(l) => {
             copy_abundances(&l.sim_abundances, &l.sim_coordinates, &l.snap_output)?;
         }
+        SubCommand::CascadeHandoff(l) => {
+            cascade_handoff(&l.sim_folder)?;
+        }
     }
     Ok(())
 }
 
-fn start_sim_set(sim_set: SimSet, args: &StartSimulation, verbose: bool) -> Result<()> {
+fn start_sim_set(args: &StartSimulation, verbose: bool) -> Result<()> {
+    let mut source = SimSetSource {
+        set_name: args.set_file.clone(),
+        overrides: args.overrides.clone(),
+        cartesian: args.cartesian,
+        ..Default::default()
+    };
+    let sim_set = SimSet::from_input_folder(&args.input_folder, &mut source)?;
     let output_sim_set = copy_sim_set(
         &sim_set,
         &args.input_folder,
         &args.output_folder,
+        &source,
         args.delete,
+        args.extend,
         args.symlink_ics,
     )?;
     build_sim_set(&output_sim_set, verbose, &args.systype)?;
@@ -127,6 +198,56 @@ fn show_sim_set(sim_set: SimSet, param_names: &[String], all: bool) -> Result<()
     Ok(())
 }
 
+fn show_manifest(manifest: &SimSetManifest) {
+    if let Some(input_folder) = &manifest.input_folder {
+        println!("Input folder: {}", input_folder);
+    }
+    if let Some(set_name) = &manifest.set_name {
+        println!("Sim set: {}", set_name);
+    }
+    for param_override in manifest.overrides.iter() {
+        println!("Override: {}", param_override);
+    }
+    if manifest.cartesian {
+        println!("Overrides combined with --cartesian");
+    }
+    for entry in manifest.sims.iter() {
+        match entry.name == entry.index.to_string() {
+            true => println!("{}:", entry.index),
+            false => println!("{} ({}):", entry.index, entry.name),
+        }
+        for (param, value) in entry.varying_params.iter() {
+            print_param_value(param, value);
+        }
+    }
+}
+
+fn show_existing_runs(sim_set: &SimSet, output_roots: &[Utf8PathBuf]) -> Result<()> {
+    for (i, runs) in find_existing_runs(sim_set, output_roots)? {
+        if runs.is_empty() {
+            println!("{}: no finished run", i);
+        }
+        for run in runs.iter() {
+            println!("{}: finished run at {}", i, run);
+        }
+    }
+    Ok(())
+}
+
+fn show_sim_set_names(folder: &Utf8Path) -> Result<()> {
+    let names = get_sim_set_names(folder)?;
+    if !names.is_empty() {
+        println!("Available sim sets: {}", names.join(", "));
+    }
+    Ok(())
+}
+
+fn show_filter_reports(filter_reports: &[FilterReport]) {
+    for report in filter_reports.iter() {
+        println!("{}", report);
+    }
+}
+
 fn print_calc_param(sim: &SimParams, param: &str) {
     match param {
         "timeUnit" => {
@@ -137,9 +258,20 @@ fn print_calc_param(sim: &SimParams, param: &str) {
     }
 }
 
-fn get_sim_set_from_input(folder: &Utf8Path) -> Result<SimSet> {
-    let config_file_path = folder.join(DEFAULT_BOB_CONFIG_NAME);
-    SimSet::from_bob_file_and_input_folder(&config_file_path, folder)
+fn get_sim_set_from_input(
+    folder: &Utf8Path,
+    set_name: Option<&str>,
+    overrides: &[ParamOverride],
+    cartesian: bool,
+) -> Result<SimSet> {
+    let config_file_path = find_bob_file(folder, set_name)?;
+    SimSet::from_bob_file_and_input_folder_with_overrides(
+        &config_file_path,
+        folder,
+        overrides,
+        cartesian,
+    )
+    .map(|(sim_set, _)| sim_set)
 }
 
 fn get_sim_set_from_output(folder: &Utf8Path) -> Result<SimSet> {
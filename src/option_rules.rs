@@ -0,0 +1,139 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::param_value::ParamValue;
+use crate::sim_set::SimSet;
+use crate::util::read_file_contents;
+
+/// Rules shipped with bob. Further rules can be given in the option rules
+/// file in the bob config folder.
+static DEFAULT_OPTION_RULES: &str = "
+- option: SWEEP
+  requires: [SX_NUM_ROT]
+- option: SWEEP_SOURCES=10
+  requires: [TestSrcFile]
+- option: TWODIMS
+  conflicts: [ONEDIMS]
+";
+
+/// A rule on the Config.sh options and parameters of a sim. Conditions are
+/// either a name, which holds if the option is enabled or the parameter is set,
+/// or "name=value", which holds if it has exactly this value.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OptionRule {
+    pub option: String,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+}
+
+pub fn read_option_rules() -> Result<Vec<OptionRule>> {
+    let mut rules: Vec<OptionRule> = serde_yaml::from_str(DEFAULT_OPTION_RULES).unwrap();
+    let xdg_dirs = xdg::BaseDirectories::with_prefix("bob")?;
+    if let Some(path) = xdg_dirs.find_config_file(config::OPTION_RULES_FILE_NAME) {
+        let path =
+            Utf8PathBuf::from_path_buf(path).map_err(|p| anyhow!("Invalid path: {:?}", p))?;
+        let contents = read_file_contents(&path)?;
+        let user_rules: Vec<OptionRule> = serde_yaml::from_str(&contents)
+            .with_context(|| format!("While reading option rules at {}", path))?;
+        rules.extend(user_rules);
+    }
+    Ok(rules)
+}
+
+fn holds<'a>(condition: &str, get: &impl Fn(&str) -> Option<&'a ParamValue>) -> bool {
+    match condition.split_once('=') {
+        Some((name, value)) => match (get(name.trim()), ParamValue::from_str(value.trim())) {
+            (Some(actual), Ok(expected)) => *actual == expected,
+            _ => false,
+        },
+        None => !matches!(
+            get(condition.trim()),
+            None | Some(ParamValue::Bool(false)) | Some(ParamValue::None)
+        ),
+    }
+}
+
+/// Returns a description of every rule that is violated by the params given by `get`.
+fn check_rules<'a>(
+    rules: &[OptionRule],
+    get: impl Fn(&str) -> Option<&'a ParamValue>,
+) -> Vec<String> {
+    let mut violations = vec![];
+    for rule in rules.iter().filter(|rule| holds(&rule.option, &get)) {
+        for required in rule.requires.iter().filter(|r| !holds(r, &get)) {
+            violations.push(format!("{} requires {}", rule.option, required));
+        }
+        for conflict in rule.conflicts.iter().filter(|c| holds(c, &get)) {
+            violations.push(format!("{} conflicts with {}", rule.option, conflict));
+        }
+    }
+    violations
+}
+
+/// Checks all sims in the sim set against the option rules.
+pub fn check_option_rules(sim_set: &SimSet) -> Result<()> {
+    let rules = read_option_rules()?;
+    let violations: Vec<String> = sim_set
+        .enumerate()
+        .flat_map(|(i, sim)| {
+            check_rules(&rules, |name| sim.get(name))
+                .into_iter()
+                .map(move |violation| format!("Sim {}: {}", i, violation))
+        })
+        .collect();
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "Option rules violated:\n{}",
+            violations.iter().join("\n")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn default_rules() {
+        let rules: Vec<OptionRule> = serde_yaml::from_str(DEFAULT_OPTION_RULES).unwrap();
+        let params: HashMap<&str, ParamValue> = [
+            ("SWEEP", ParamValue::Bool(true)),
+            ("SWEEP_SOURCES", ParamValue::Int(10)),
+            ("TWODIMS", ParamValue::Bool(true)),
+            ("ONEDIMS", ParamValue::Bool(false)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            check_rules(&rules, |name| params.get(name)),
+            [
+                "SWEEP requires SX_NUM_ROT",
+                "SWEEP_SOURCES=10 requires TestSrcFile"
+            ]
+        );
+        let params: HashMap<&str, ParamValue> = [
+            ("SWEEP_SOURCES", ParamValue::Int(1)),
+            ("TWODIMS", ParamValue::Bool(true)),
+            ("ONEDIMS", ParamValue::Bool(true)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            check_rules(&rules, |name| params.get(name)),
+            ["TWODIMS conflicts with ONEDIMS"]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::str::FromStr;
+
+use crate::config;
+use crate::param_value::ParamValue;
+
+enum Line {
+    Param {
+        key: String,
+        /// Everything up to the start of the value
+        before_value: String,
+        value: String,
+        /// The whitespace and comment after the value
+        after_value: String,
+    },
+    Other(String),
+}
+
+/// The lines of a param file, including comments, section headers and the
+/// alignment of values, so that it can be written back with only the changed
+/// values differing from the original.
+pub struct ParamFile {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+impl ParamFile {
+    pub fn parse(contents: &str) -> ParamFile {
+        ParamFile {
+            lines: contents.lines().map(parse_line).collect(),
+            trailing_newline: contents.ends_with('\n'),
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.lines
+            .iter()
+            .any(|line| matches!(line, Line::Param { key: k, .. } if k == key))
+    }
+
+    /// Sets the value of the parameter, keeping the original text if it
+    /// represents the same value. Appends the parameter if it does not exist yet.
+    pub fn set(&mut self, key: &str, new_value: &ParamValue) {
+        let new_text = new_value.to_string();
+        for line in self.lines.iter_mut() {
+            if let Line::Param {
+                key: k,
+                value,
+                after_value,
+                ..
+            } = line
+            {
+                if k != key {
+                    continue;
+                }
+                if ParamValue::from_str(value).ok().as_ref() != Some(new_value) {
+                    *after_value = realign(after_value, value.len(), new_text.len());
+                    *value = new_text;
+                }
+                return;
+            }
+        }
+        if !self.lines.iter().any(
+            |line| matches!(line, Line::Other(l) if l == config::PARAM_FILE_APPENDED_SECTION_HEADER),
+        ) {
+            self.lines.push(Line::Other("".into()));
+            self.lines.push(Line::Other(
+                config::PARAM_FILE_APPENDED_SECTION_HEADER.into(),
+            ));
+        }
+        self.lines.push(Line::Param {
+            key: key.into(),
+            before_value: format!("{}    ", key),
+            value: new_text,
+            after_value: "".into(),
+        });
+    }
+
+    /// Removes the lines which set the parameter.
+    pub fn remove(&mut self, key: &str) {
+        self.lines
+            .retain(|line| !matches!(line, Line::Param { key: k, .. } if k == key));
+    }
+}
+
+impl std::fmt::Display for ParamFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match line {
+                Line::Param {
+                    before_value,
+                    value,
+                    after_value,
+                    ..
+                } => write!(f, "{}{}{}", before_value, value, after_value)?,
+                Line::Other(line) => write!(f, "{}", line)?,
+            }
+        }
+        if self.trailing_newline {
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Line {
+    let is_separator = |c: char| c.is_whitespace() || c == '%';
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('%') {
+        return Line::Other(line.into());
+    }
+    let key_start = line.len() - trimmed.len();
+    let key_end = key_start + trimmed.find(is_separator).unwrap_or(trimmed.len());
+    let value_start = key_end
+        + line[key_end..]
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(0);
+    if value_start == key_end || line[value_start..].starts_with('%') {
+        return Line::Other(line.into());
+    }
+    let value_end = value_start
+        + line[value_start..]
+            .find(is_separator)
+            .unwrap_or(line.len() - value_start);
+    Line::Param {
+        key: line[key_start..key_end].into(),
+        before_value: line[..value_start].into(),
+        value: line[value_start..value_end].into(),
+        after_value: line[value_end..].into(),
+    }
+}
+
+/// Keeps a trailing comment in the same column if the new value leaves room for it.
+fn realign(after_value: &str, old_len: usize, new_len: usize) -> String {
+    let comment = after_value.trim_start();
+    if comment.is_empty() {
+        return "".into();
+    }
+    let spaces = after_value.len() - comment.len();
+    let new_spaces = (spaces + old_len).saturating_sub(new_len).max(1);
+    format!("{}{}", " ".repeat(new_spaces), comment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_keeps_structure() {
+        let contents = "%---- Relevant files\nInitCondFile    ics   % the ics\nOutputDir       output\n\nTimeMax         1.0\n";
+        let mut file = ParamFile::parse(contents);
+        assert_eq!(file.to_string(), contents);
+        file.set("TimeMax", &"1.0".parse().unwrap());
+        file.set("InitCondFile", &ParamValue::Str("ics2".into()));
+        file.set("SnapshotFileBase", &ParamValue::Str("snap".into()));
+        assert!(file.contains_key("SnapshotFileBase"));
+        file.remove("OutputDir");
+        assert!(!file.contains_key("OutputDir"));
+        assert_eq!(
+            file.to_string(),
+            format!(
+                "%---- Relevant files\nInitCondFile    ics2  % the ics\n\nTimeMax         1.0\n\n{}\nSnapshotFileBase    snap\n",
+                config::PARAM_FILE_APPENDED_SECTION_HEADER
+            )
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_yaml::Value;
+
+use crate::param_value::ParamValue;
+
+/// A substitution given on the command line as "param=value" or
+/// "param=value1,value2,...", which takes precedence over the bob file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParamOverride {
+    pub param: String,
+    pub value: Value,
+}
+
+impl std::fmt::Display for ParamOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = match &self.value {
+            Value::Sequence(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let values: Vec<String> = values
+            .into_iter()
+            .map(|value| match ParamValue::new(value) {
+                Ok(value) => value.to_string(),
+                Err(_) => format!("{:?}", value),
+            })
+            .collect();
+        write!(f, "{}={}", self.param, values.join(","))
+    }
+}
+
+impl FromStr for ParamOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ParamOverride> {
+        let (param, values) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected param=value or param=value1,value2: {}", s))?;
+        let param = param.trim();
+        if param.is_empty() {
+            return Err(anyhow!("Missing parameter name in {}", s));
+        }
+        let values: Vec<Value> = values
+            .split(',')
+            .map(|value| serde_yaml::from_str(value.trim()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| anyhow!("Invalid value in {}: {}", s, e))?;
+        let value = match values.len() {
+            1 => values.into_iter().next().unwrap(),
+            _ => Value::Sequence(values),
+        };
+        Ok(ParamOverride {
+            param: param.into(),
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_override() -> Result<()> {
+        let o: ParamOverride = "numCores=4,8,16".parse()?;
+        assert_eq!(o.param, "numCores");
+        assert_eq!(o.value, serde_yaml::to_value([4, 8, 16])?);
+        let o: ParamOverride = "SWEEP=true".parse()?;
+        assert_eq!(o.value, Value::Bool(true));
+        assert!("SWEEP".parse::<ParamOverride>().is_err());
+        assert_eq!("a=1,2".parse::<ParamOverride>()?.to_string(), "a=1,2");
+        Ok(())
+    }
+}
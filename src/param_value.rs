@@ -13,6 +13,14 @@ pub enum ParamValue {
     Int(i64),
     Float(OrderedFloat<f64>, String), // Keep the original string representation to make sure we dont change anything
     Bool(bool),
+    /// A value that was given with a unit: the value in code units and the original string
+    Quantity(OrderedFloat<f64>, String),
+    /// A list of values, such as one value per frequency bin. Since a list in the
+    /// substitutions of a bob file is a sweep, list values are given as lists
+    /// inside it, as in `rates: [[1, 2, 3]]`. Written as a comma separated list
+    /// without spaces so that Arepo reads it as a single token. Lists are never
+    /// parsed from strings, so the written value reads back as a string.
+    List(Vec<ParamValue>),
     None,
 }
 
@@ -23,6 +31,16 @@ impl std::fmt::Display for ParamValue {
             ParamValue::Int(x) => write!(f, "{}", x),
             ParamValue::Float(_, s) => write!(f, "{}", s),
             ParamValue::Bool(x) => write!(f, "{}", x),
+            ParamValue::Quantity(x, _) => write!(f, "{}", **x),
+            ParamValue::List(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", LIST_SEPARATOR)?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
             ParamValue::None => write!(f, "None"),
         }
     }
@@ -31,9 +49,9 @@ impl std::fmt::Display for ParamValue {
 impl ParamValue {
     pub fn new(v: &Value) -> Result<ParamValue> {
         match v {
-            Value::Null => {
-                panic!("Null value in serde value");
-            }
+            Value::Null => Err(anyhow!(
+                "Missing value (null) - invalid bob file structure?"
+            )),
             Value::Bool(x) => Ok(ParamValue::Bool(*x)),
             Value::Number(x) => {
                 if x.is_i64() {
@@ -54,8 +72,20 @@ impl ParamValue {
                     Ok(ParamValue::Str(x.as_str().to_owned()))
                 }
             }
-            Value::Sequence(_) => Err(anyhow!("List in serde value - invalid bob file structure?")),
-            Value::Mapping(_) => panic!("Mapping in serde value!"),
+            Value::Sequence(values) => Ok(ParamValue::List(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Sequence(_) => Err(anyhow!(
+                            "Nested list in serde value - invalid bob file structure?"
+                        )),
+                        value => ParamValue::new(value),
+                    })
+                    .collect::<Result<_>>()?,
+            )),
+            Value::Mapping(_) => Err(anyhow!(
+                "Mapping in serde value - invalid bob file structure?"
+            )),
         }
     }
 
@@ -65,7 +95,7 @@ impl ParamValue {
 
     pub fn unwrap_f64(&self) -> f64 {
         match self {
-            ParamValue::Float(f, _) => **f,
+            ParamValue::Float(f, _) | ParamValue::Quantity(f, _) => **f,
             ParamValue::Int(i) => *i as f64,
             _ => panic!("Tried to read value {} as float.", self),
         }
@@ -98,7 +128,9 @@ impl ParamValue {
             ParamValue::Bool(_)
             | ParamValue::Str(_)
             | ParamValue::Int(_)
-            | ParamValue::Float(_, _) => Some(self),
+            | ParamValue::Float(_, _)
+            | ParamValue::Quantity(_, _)
+            | ParamValue::List(_) => Some(self),
         }
     }
 
@@ -108,11 +140,23 @@ impl ParamValue {
             ParamValue::Bool(_)
             | ParamValue::Str(_)
             | ParamValue::Int(_)
-            | ParamValue::Float(_, _) => Some(self),
+            | ParamValue::Float(_, _)
+            | ParamValue::Quantity(_, _)
+            | ParamValue::List(_) => Some(self),
+        }
+    }
+
+    /// Replaces a value given with a unit by the plain number in code units.
+    pub fn without_unit(self) -> ParamValue {
+        match self {
+            ParamValue::Quantity(x, _) => ParamValue::new_float(*x),
+            value => value,
         }
     }
 }
 
+static LIST_SEPARATOR: char = ',';
+
 impl FromStr for ParamValue {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<ParamValue> {
@@ -127,3 +171,26 @@ impl FromStr for ParamValue {
             .or_else(|_| Ok(ParamValue::Str(s.to_string())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_values() -> Result<()> {
+        let value = ParamValue::new(&serde_yaml::from_str("[1, 0.5, true]")?)?;
+        assert_eq!(
+            value,
+            ParamValue::List(vec![
+                ParamValue::Int(1),
+                ParamValue::Float(OrderedFloat(0.5), "0.5".into()),
+                ParamValue::Bool(true)
+            ])
+        );
+        assert_eq!(value.to_string(), "1,0.5,true");
+        assert!(ParamValue::new(&serde_yaml::from_str("[[1, 2]]")?).is_err());
+        // Lists only come from bob files, strings with commas stay strings
+        assert_eq!(ParamValue::from_str("1,2")?, ParamValue::Str("1,2".into()));
+        Ok(())
+    }
+}
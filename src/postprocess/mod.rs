@@ -10,6 +10,7 @@ use self::postprocess_args::PostprocessArgs;
 use crate::config;
 use crate::config::DEFAULT_PIC_FOLDER;
 use crate::sim_params::SimParams;
+use crate::sim_set::filter_sim_set;
 use crate::sim_set::SimSet;
 use crate::source_file::SourceFile;
 use crate::thread_pool::ThreadPool;
@@ -48,7 +49,7 @@ pub fn postprocess_sim_set(
     sim_set: SimSet,
     args: &PostprocessArgs,
 ) -> Result<()> {
-    let sim_set = filter_sim_set(sim_set, args.select.as_ref());
+    let sim_set = filter_sim_set(sim_set, args.select.as_ref(), args.condition.as_ref())?;
     let data_plot_info_iter = args.function.run(&sim_set, args.plot_template.as_deref());
     let mut pool: ThreadPool<anyhow::Result<()>, _> = ThreadPool::new(config::MAX_NUM_POST_THREADS);
     for data_plot_info in data_plot_info_iter {
@@ -79,17 +80,6 @@ pub fn postprocess_sim_set(
     Ok(())
 }
 
-fn filter_sim_set(sim_set: SimSet, select: Option<&Vec<usize>>) -> SimSet {
-    if let Some(selected_sims) = select {
-        sim_set
-            .into_iter()
-            .filter(|(num, _)| selected_sims.contains(num))
-            .collect()
-    } else {
-        sim_set
-    }
-}
-
 pub fn write_results(
     data_folder: &Utf8Path,
     data_plot_info: &DataPlotInfo,
@@ -15,6 +15,7 @@ use super::post_fn::PostResult;
 use super::snapshot::Snapshot;
 use crate::array_utils::FArray1;
 use crate::array_utils::FArray2;
+use crate::bisect::bisect;
 use crate::config;
 use crate::postprocess::data_plot_info::DataPlotInfo;
 use crate::set_function;
@@ -87,7 +88,7 @@ fn get_expansion_data(sim_set: &SimSet) -> Result<PostResult> {
     params.add("maxY", result[0][[num_snaps - 1, 1]].max(1.0));
     params.add("stroemgrenRadius", (stroemgren_radius / kpc).value);
     params.add("recombinationTime", (recombination_time / megayear).value);
-    Ok(PostResult::new(params, result))
+    Ok(PostResult::per_sim(params, result))
 }
 
 fn get_radius(snap: &Snapshot, source_pos: &FArray1, box_size: f64) -> Result<Length> {
@@ -118,30 +119,6 @@ fn get_radius_code_units(snap: &Snapshot, source_pos: &FArray1, box_size: f64) -
     ))
 }
 
-fn bisect(f: impl Fn(f64) -> f64, y_target: f64, treshold: f64, x_min: f64, x_max: f64) -> f64 {
-    bisect_to_value(f, y_target, treshold, x_min, x_max, 0, 15)
-}
-
-fn bisect_to_value(
-    f: impl Fn(f64) -> f64,
-    y_target: f64,
-    treshold: f64,
-    x_min: f64,
-    x_max: f64,
-    depth: usize,
-    max_depth: usize,
-) -> f64 {
-    let x_try = (x_max + x_min) / 2.0;
-    let y = f(x_try);
-    if (y - y_target).abs() < treshold || depth > max_depth {
-        x_try
-    } else if y > y_target {
-        bisect_to_value(f, y_target, treshold, x_min, x_try, depth + 1, max_depth)
-    } else {
-        bisect_to_value(f, y_target, treshold, x_try, x_max, depth + 1, max_depth)
-    }
-}
-
 fn get_mean_abundance_at_radius(
     coordinates: &FArray2,
     h_plus_abundance: &FArray1,
@@ -1,5 +1,12 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+use super::data_plot_info::DataPlotInfo;
 use super::plot_params::PlotParams;
 use crate::array_utils::FArray2;
+use crate::sim_set::SimSet;
 
 pub enum PostFnKind {
     Snap,
@@ -11,18 +18,31 @@ pub enum PostFnKind {
 pub struct PostResult {
     pub params: PlotParams,
     pub data: Vec<FArray2>,
+    /// Whether `data` contains one curve per sim, in the order of the sim set
+    pub per_sim: bool,
 }
 
 impl PostResult {
     pub fn new(params: PlotParams, data: Vec<FArray2>) -> Self {
-        Self { params, data }
+        Self {
+            params,
+            data,
+            per_sim: false,
+        }
+    }
+
+    /// A result containing one curve per sim, in the order of the sim set. The curves
+    /// of the replicas of each ensemble are combined by `into_ensemble_statistics`.
+    pub fn per_sim(params: PlotParams, data: Vec<FArray2>) -> Self {
+        Self {
+            params,
+            data,
+            per_sim: true,
+        }
     }
 
     pub fn join(results: Vec<PostResult>) -> PostResult {
-        let mut final_result = PostResult {
-            params: PlotParams::default(),
-            data: vec![],
-        };
+        let mut final_result = PostResult::new(PlotParams::default(), vec![]);
         for (i, result) in results.into_iter().enumerate() {
             final_result.data.extend(result.data);
             for (k, v) in result.params.0.iter() {
@@ -34,21 +54,171 @@ impl PostResult {
         }
         final_result
     }
+
+    /// For sim sets containing replicas, replaces the curves of the replicas of each ensemble
+    /// by a single curve (see `get_mean_and_std`). Only applies to results created with
+    /// `PostResult::per_sim`, other results are returned unchanged.
+    pub fn into_ensemble_statistics(self, sim_set: &SimSet) -> Result<PostResult> {
+        if !self.per_sim || !sim_set.has_replicas() {
+            return Ok(self);
+        }
+        if self.data.len() != sim_set.len() {
+            return Err(anyhow!(
+                "Expected one curve per sim, found {} curves for {} sims",
+                self.data.len(),
+                sim_set.len()
+            ));
+        }
+        let folders: Vec<_> = sim_set.iter().map(|sim| &sim.folder).collect();
+        let data = sim_set
+            .ensembles()
+            .iter()
+            .map(|ensemble| {
+                let curves: Vec<&FArray2> = ensemble
+                    .iter()
+                    .map(|sim| {
+                        let position = folders.iter().position(|f| **f == sim.folder).unwrap();
+                        &self.data[position]
+                    })
+                    .collect();
+                get_mean_and_std(&curves)
+            })
+            .collect::<Result<_>>()?;
+        Ok(PostResult::new(self.params, data))
+    }
+}
+
+/// For sim sets containing replicas, combines the results of the snapshots of the replicas
+/// of each ensemble into one result per snapshot, which contains the element-wise mean of
+/// each array of data followed by the element-wise standard deviation of each array.
+/// `keys` contains the position of the sim within the sim set and the index of the snapshot
+/// for each of the results.
+pub fn into_snap_ensemble_statistics(
+    sim_set: &SimSet,
+    keys: Vec<(usize, usize)>,
+    results: Vec<Result<DataPlotInfo>>,
+) -> Vec<Result<DataPlotInfo>> {
+    if !sim_set.has_replicas() {
+        return results;
+    }
+    let mut results: HashMap<(usize, usize), Result<DataPlotInfo>> =
+        keys.into_iter().zip(results).collect();
+    let folders: Vec<_> = sim_set.iter().map(|sim| &sim.folder).collect();
+    let mut combined = vec![];
+    for ensemble in sim_set.ensembles() {
+        let positions: Vec<usize> = ensemble
+            .iter()
+            .map(|sim| folders.iter().position(|f| **f == sim.folder).unwrap())
+            .collect();
+        for snap_index in 0.. {
+            if !positions
+                .iter()
+                .all(|position| results.contains_key(&(*position, snap_index)))
+            {
+                break;
+            }
+            combined.push(
+                positions
+                    .iter()
+                    .map(|position| results.remove(&(*position, snap_index)).unwrap())
+                    .collect::<Result<Vec<_>>>()
+                    .and_then(get_elementwise_mean_and_std),
+            );
+        }
+    }
+    if !results.is_empty() {
+        combined.push(Err(anyhow!(
+            "Replicas of an ensemble have different numbers of snapshots"
+        )));
+    }
+    combined
+}
+
+fn get_elementwise_mean_and_std(infos: Vec<DataPlotInfo>) -> Result<DataPlotInfo> {
+    let num_arrays = infos[0].data.len();
+    if infos.iter().any(|info| info.data.len() != num_arrays) {
+        return Err(anyhow!(
+            "Results of replicas contain different numbers of arrays"
+        ));
+    }
+    let mut means = vec![];
+    let mut stds = vec![];
+    for j in 0..num_arrays {
+        let arrays: Vec<&FArray2> = infos.iter().map(|info| &info.data[j]).collect();
+        let shape = arrays[0].shape();
+        if arrays.iter().any(|array| array.shape() != shape) {
+            return Err(anyhow!("Results of replicas have different shapes"));
+        }
+        let mut mean = FArray2::zeros((shape[0], shape[1]));
+        let mut std = FArray2::zeros((shape[0], shape[1]));
+        for ((i, k), value) in mean.indexed_iter_mut() {
+            let (m, s) = get_mean_and_std_of_values(arrays.iter().map(|array| array[[i, k]]));
+            *value = m;
+            std[[i, k]] = s;
+        }
+        means.push(mean);
+        stds.push(std);
+    }
+    let mut infos = infos.into_iter();
+    let first = infos.next().unwrap();
+    means.extend(stds);
+    Ok(DataPlotInfo {
+        info: first.info,
+        data: means,
+        replacements: first.replacements,
+    })
+}
+
+/// Combines curves with the columns x, y1, y2, ... into a single curve with the columns
+/// x, mean of y1, standard deviation of y1, mean of y2, standard deviation of y2, ...
+fn get_mean_and_std(curves: &[&FArray2]) -> Result<FArray2> {
+    let shape = curves[0].shape();
+    if curves.iter().any(|curve| curve.shape() != shape) {
+        return Err(anyhow!(
+            "Curves of replicas have different numbers of points"
+        ));
+    }
+    let num_columns = 1 + 2 * (shape[1] - 1);
+    let mut result = FArray2::zeros((shape[0], num_columns));
+    for i in 0..shape[0] {
+        result[[i, 0]] = curves[0][[i, 0]];
+        for column in 1..shape[1] {
+            let (mean, std) =
+                get_mean_and_std_of_values(curves.iter().map(|curve| curve[[i, column]]));
+            result[[i, 2 * column - 1]] = mean;
+            result[[i, 2 * column]] = std;
+        }
+    }
+    Ok(result)
+}
+
+/// The mean and sample standard deviation (zero for a single value).
+fn get_mean_and_std_of_values(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+    let n = values.clone().count() as f64;
+    let mean = values.clone().sum::<f64>() / n;
+    let variance = match n as usize {
+        1 => 0.0,
+        _ => values.map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0),
+    };
+    (mean, variance.sqrt())
 }
 
 #[macro_export]
 macro_rules! snap_function {
     ($i:ident, $code:block) => {
-        pub fn run($i: &Self, sim_set: &SimSet, plot_template: Option<&str>) -> impl Iterator<Item=Result<DataPlotInfo>>  {
+        pub fn run($i: &Self, sim_set: &SimSet, plot_template: Option<&str>) -> Box<dyn Iterator<Item=Result<DataPlotInfo>>>  {
             use crate::postprocess::data_plot_info::DataPlotInfo;
             use crate::postprocess::snapshot::Snapshot;
             use crate::thread_pool::ThreadPool;
             use crate::config::MAX_NUM_POST_THREADS;
             use crate::postprocess::get_snapshot_files;
+            use crate::postprocess::post_fn::into_snap_ensemble_statistics;
             let mut pool = ThreadPool::new(MAX_NUM_POST_THREADS);
             let mut infos = vec![];
-            for sim in sim_set.iter() {
-                for snap_path in get_snapshot_files(sim).unwrap() {
+            let mut keys = vec![];
+            for (sim_position, sim) in sim_set.iter().enumerate() {
+                for (snap_index, snap_path) in get_snapshot_files(sim).unwrap().enumerate() {
+                    keys.push((sim_position, snap_index));
                     let snap = Snapshot::from_file(&sim, &snap_path).unwrap();
                     let info = $i.get_plot_info(Some(&sim), Some(&snap), plot_template).unwrap();
                     let sim = sim.clone();
@@ -62,11 +232,15 @@ macro_rules! snap_function {
                     });
                 }
             }
-            infos.into_iter().zip(pool).map(|(info, result)|
+            let results = infos.into_iter().zip(pool).map(|(info, result)|
                                             result.map(|result| {
                                                 DataPlotInfo::new(info, result)}
                                             )
-            )
+            );
+            match sim_set.has_replicas() {
+                true => Box::new(into_snap_ensemble_statistics(sim_set, keys, results.collect()).into_iter()),
+                false => Box::new(results),
+            }
         }
     }
 }
@@ -94,9 +268,104 @@ macro_rules! set_function {
             sim_set: &SimSet,
             plot_template: Option<&str>,
         ) -> impl Iterator<Item = Result<DataPlotInfo>> {
-            let result = $code(sim_set);
+            let result =
+                $code(sim_set).and_then(|result: $crate::postprocess::post_fn::PostResult| {
+                    result.into_ensemble_statistics(sim_set)
+                });
             let info = $i.get_plot_info(None, None, plot_template).unwrap();
             vec![result.map(|result| DataPlotInfo::new(info, result))].into_iter()
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+    use ndarray::array;
+
+    use super::*;
+    use crate::config;
+    use crate::param_value::ParamValue;
+    use crate::postprocess::plot_info::PlotInfo;
+    use crate::sim_params::SimParamsKind;
+    use crate::test_utils::sim_params_in;
+
+    /// Two ensembles (a = 1 and a = 2) with two replicas each.
+    fn sim_set() -> SimSet {
+        let sim = |i: usize, a: i64, replica: i64| {
+            sim_params_in(
+                &Utf8PathBuf::from(i.to_string()),
+                SimParamsKind::Output,
+                &[
+                    ("a", ParamValue::Int(a)),
+                    (config::REPLICA_IDENTIFIER, ParamValue::Int(replica)),
+                ],
+            )
+        };
+        [(1, 0), (1, 1), (2, 0), (2, 1)]
+            .iter()
+            .enumerate()
+            .map(|(i, (a, replica))| (i, sim(i, *a, *replica)))
+            .collect()
+    }
+
+    #[test]
+    fn ensemble_statistics_keep_all_columns() -> Result<()> {
+        let curve = |y1: f64, y2: f64| array![[0.0, y1, y2], [1.0, 2.0 * y1, 2.0 * y2]];
+        let result = PostResult::per_sim(
+            PlotParams::default(),
+            vec![
+                curve(1.0, 10.0),
+                curve(3.0, 10.0),
+                curve(5.0, 20.0),
+                curve(5.0, 40.0),
+            ],
+        )
+        .into_ensemble_statistics(&sim_set())?;
+        let std = 2.0f64.sqrt();
+        assert_eq!(result.data.len(), 2);
+        assert_eq!(
+            result.data[0],
+            array![[0.0, 2.0, std, 10.0, 0.0], [1.0, 4.0, 2.0 * std, 20.0, 0.0]]
+        );
+        assert_eq!(
+            result.data[1].row(0),
+            array![0.0, 5.0, 0.0, 30.0, 10.0 * std]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_results_are_unchanged() -> Result<()> {
+        let curves: Vec<FArray2> = (0..4).map(|i| array![[0.0, i as f64]]).collect();
+        let result = PostResult::new(PlotParams::default(), curves.clone())
+            .into_ensemble_statistics(&sim_set())?;
+        assert_eq!(result.data, curves);
+        Ok(())
+    }
+
+    #[test]
+    fn snap_ensemble_statistics() {
+        let info = |value: f64| {
+            Ok(DataPlotInfo {
+                info: PlotInfo::new("slice", "slice", None, None, None),
+                data: vec![array![[value, 2.0 * value]]],
+                replacements: HashMap::new(),
+            })
+        };
+        // Two snapshots for each sim
+        let keys: Vec<(usize, usize)> = (0..4).flat_map(|sim| [(sim, 0), (sim, 1)]).collect();
+        let results = (0..8).map(|i| info(i as f64)).collect();
+        let combined: Vec<DataPlotInfo> = into_snap_ensemble_statistics(&sim_set(), keys, results)
+            .into_iter()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(combined.len(), 4);
+        // Snapshot 1 of sims 0 and 1 have the values 1 and 3
+        assert_eq!(combined[1].data[0], array![[2.0, 4.0]]);
+        assert_eq!(
+            combined[1].data[1],
+            array![[2.0f64.sqrt(), 2.0 * 2.0f64.sqrt()]]
+        );
+    }
+}
@@ -42,12 +42,7 @@ impl ScalingFn {
         let mut sub_sim_sets = sim_set.quotients(&params);
         sub_sim_sets.sort_by_key(|set| {
             (
-                set.iter()
-                    .next()
-                    .unwrap()
-                    .get("SWEEP")
-                    .unwrap()
-                    .unwrap_bool(),
+                set.iter().next().unwrap().get_default_bool("SWEEP", false),
                 set.iter().map(|sim| sim.get_num_cores().unwrap()).min(),
             )
         });
@@ -2,6 +2,7 @@ use camino::Utf8PathBuf;
 use clap::Clap;
 
 use super::post_fn_name::PostFnName;
+use crate::expression::Expr;
 
 /// Run the postprocessing scripts
 #[derive(Clap, Debug)]
@@ -10,6 +11,9 @@ pub struct PostprocessArgs {
     pub showall: bool,
     #[clap(long, global = true)]
     pub select: Option<Vec<usize>>,
+    /// Only use the sims for which this condition holds, e.g. 'numCores>=64 && SWEEP==true'
+    #[clap(long = "where", global = true)]
+    pub condition: Option<Expr>,
     pub output_folders: Vec<Utf8PathBuf>,
     #[clap(long)]
     pub plot_template: Option<String>,
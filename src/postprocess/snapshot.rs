@@ -1,3 +1,4 @@
+use anyhow::Context;
 use anyhow::Result;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
@@ -83,6 +84,23 @@ impl<'a> Snapshot<'a> {
         Ok(self.file.dataset(dataset)?.read()?)
     }
 
+    /// Returns the name and number of entries of every dataset of the gas cells,
+    /// without reading their data.
+    pub fn read_gas_dataset_lengths(&self) -> Result<Vec<(String, usize)>> {
+        let group = self.file.group("PartType0")?;
+        group
+            .member_names()?
+            .into_iter()
+            .map(|name| {
+                let dataset = group
+                    .dataset(&name)
+                    .with_context(|| format!("While opening dataset PartType0/{}", name))?;
+                let length = dataset.shape().first().copied().unwrap_or(1);
+                Ok((name, length))
+            })
+            .collect()
+    }
+
     pub fn get_header_attribute<Q>(&self, name: &str, unit: Q) -> Result<Q>
     where
         Q: Clone + std::ops::Mul<f64, Output = Q>,
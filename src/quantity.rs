@@ -0,0 +1,216 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use uom::si::energy::erg;
+use uom::si::energy::joule;
+use uom::si::f64::Energy;
+use uom::si::f64::Length;
+use uom::si::f64::Mass;
+use uom::si::f64::Time;
+use uom::si::length::astronomical_unit;
+use uom::si::length::centimeter;
+use uom::si::length::kilometer;
+use uom::si::length::light_year;
+use uom::si::length::meter;
+use uom::si::length::parsec;
+use uom::si::mass::gram;
+use uom::si::mass::kilogram;
+use uom::si::time::day;
+use uom::si::time::second;
+use uom::si::time::year;
+
+use crate::sim_units::SimUnits;
+
+static SOLAR_MASS_IN_G: f64 = 1.98847e33;
+
+/// The dimension of a quantity, as powers of length, mass and time.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Dimension {
+    length: i32,
+    mass: i32,
+    time: i32,
+}
+
+impl Dimension {
+    fn times(self, other: Dimension, power: i32) -> Dimension {
+        Dimension {
+            length: self.length + other.length * power,
+            mass: self.mass + other.mass * power,
+            time: self.time + other.time * power,
+        }
+    }
+}
+
+/// A value with a unit, such as "13.2 kpc" or "5e48 1/s".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    cgs_value: f64,
+    dimension: Dimension,
+}
+
+impl Quantity {
+    /// Returns the value of the quantity in the code units of a sim.
+    pub fn to_code_units(&self, units: &SimUnits) -> f64 {
+        let unit_value = units.length.get::<centimeter>().powi(self.dimension.length)
+            * units.mass.get::<gram>().powi(self.dimension.mass)
+            * units.time.get::<second>().powi(self.dimension.time);
+        self.cgs_value / unit_value
+    }
+
+    /// Returns the quantity if the string is a number followed by a known unit.
+    pub fn parse_if_quantity(s: &str) -> Option<Quantity> {
+        let (number, unit) = s.trim().split_once(char::is_whitespace)?;
+        let unit = unit.trim();
+        let looks_like_unit = unit.starts_with(char::is_alphabetic) || unit.starts_with("1/");
+        match number.parse::<f64>() {
+            Ok(_) if looks_like_unit => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Quantity> {
+        let (number, unit) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("Expected a number followed by a unit: {}", s))?;
+        let value: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("Invalid number in {}", s))?;
+        let (factor, dimension) =
+            parse_unit(unit.trim()).map_err(|e| anyhow!("{} (in {})", e, s))?;
+        Ok(Quantity {
+            cgs_value: value * factor,
+            dimension,
+        })
+    }
+}
+
+/// Parses products and quotients of units with optional integer
+/// powers, such as "km/s", "g/cm^3", "erg / s" or "1/s".
+fn parse_unit(unit: &str) -> Result<(f64, Dimension)> {
+    let mut factor = 1.0;
+    let mut dimension = Dimension::default();
+    let mut sign = 1;
+    for token in unit
+        .replace('/', " / ")
+        .replace('*', " ")
+        .split_whitespace()
+    {
+        if token == "/" {
+            sign = -1;
+            continue;
+        }
+        let (name, power) = match token.split_once('^') {
+            Some((name, power)) => (
+                name,
+                power
+                    .parse::<i32>()
+                    .map_err(|_| anyhow!("Invalid power in unit: {}", token))?,
+            ),
+            None => (token, 1),
+        };
+        let (unit_factor, unit_dimension) =
+            get_unit(name).ok_or_else(|| anyhow!("Unknown unit: {}", name))?;
+        factor *= unit_factor.powi(sign * power);
+        dimension = dimension.times(unit_dimension, sign * power);
+        sign = 1;
+    }
+    Ok((factor, dimension))
+}
+
+/// Returns the value of the unit in cgs units and its dimension.
+fn get_unit(name: &str) -> Option<(f64, Dimension)> {
+    let length = |l: Length| {
+        let dimension = Dimension {
+            length: 1,
+            ..Default::default()
+        };
+        (l.get::<centimeter>(), dimension)
+    };
+    let mass = |m: Mass| {
+        let dimension = Dimension {
+            mass: 1,
+            ..Default::default()
+        };
+        (m.get::<gram>(), dimension)
+    };
+    let time = |t: Time| {
+        let dimension = Dimension {
+            time: 1,
+            ..Default::default()
+        };
+        (t.get::<second>(), dimension)
+    };
+    let energy = |e: Energy| {
+        let dimension = Dimension {
+            length: 2,
+            mass: 1,
+            time: -2,
+        };
+        (e.get::<erg>(), dimension)
+    };
+    Some(match name {
+        "1" => (1.0, Dimension::default()),
+        "cm" => length(Length::new::<centimeter>(1.0)),
+        "m" => length(Length::new::<meter>(1.0)),
+        "km" => length(Length::new::<kilometer>(1.0)),
+        "au" => length(Length::new::<astronomical_unit>(1.0)),
+        "ly" => length(Length::new::<light_year>(1.0)),
+        "pc" => length(Length::new::<parsec>(1.0)),
+        "kpc" => length(Length::new::<parsec>(1e3)),
+        "Mpc" => length(Length::new::<parsec>(1e6)),
+        "g" => mass(Mass::new::<gram>(1.0)),
+        "kg" => mass(Mass::new::<kilogram>(1.0)),
+        "Msun" => mass(Mass::new::<gram>(SOLAR_MASS_IN_G)),
+        "s" => time(Time::new::<second>(1.0)),
+        "d" => time(Time::new::<day>(1.0)),
+        "yr" => time(Time::new::<year>(1.0)),
+        "kyr" => time(Time::new::<year>(1e3)),
+        "Myr" => time(Time::new::<year>(1e6)),
+        "Gyr" => time(Time::new::<year>(1e9)),
+        "erg" => energy(Energy::new::<erg>(1.0)),
+        "J" => energy(Energy::new::<joule>(1.0)),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::f64::Velocity;
+    use uom::si::velocity::centimeter_per_second;
+
+    use super::*;
+
+    fn assert_close(x: f64, y: f64) {
+        assert!((x / y - 1.0).abs() < 1e-10, "{} != {}", x, y);
+    }
+
+    #[test]
+    fn convert_to_code_units() -> Result<()> {
+        let units = SimUnits::new(
+            Length::new::<parsec>(1e3),
+            Velocity::new::<centimeter_per_second>(1e5),
+            Mass::new::<gram>(SOLAR_MASS_IN_G),
+        );
+        let kpc: Quantity = "13.2 kpc".parse()?;
+        assert_close(kpc.to_code_units(&units), 13.2);
+        let velocity: Quantity = "3 km/s".parse()?;
+        assert_close(velocity.to_code_units(&units), 3.0);
+        let rate: Quantity = "5e48 1/s".parse()?;
+        assert_close(
+            rate.to_code_units(&units),
+            5e48 * Length::new::<parsec>(1e3).get::<centimeter>() / 1e5,
+        );
+        let density: Quantity = "1 Msun / kpc^3".parse()?;
+        assert_close(density.to_code_units(&units), 1.0);
+        assert!(Quantity::parse_if_quantity("13.2 apples").is_none());
+        assert!(Quantity::parse_if_quantity("21 5").is_none());
+        assert!(Quantity::parse_if_quantity("output").is_none());
+        Ok(())
+    }
+}
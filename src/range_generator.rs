@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_yaml::Number;
+use serde_yaml::Value;
+
+/// Generates a list of parameter values from a short description,
+/// written in the bob file as for example `{logspace: [1e48, 1e52, 9]}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum RangeGenerator {
+    /// `num` evenly spaced values from `start` to `stop` (inclusive)
+    Linspace(Number, Number, usize),
+    /// `num` logarithmically spaced values from `start` to `stop` (inclusive)
+    Logspace(Number, Number, usize),
+    /// Values from `start` to `stop` (exclusive) in increments of `step`
+    Range(Number, Number, Number),
+}
+
+impl RangeGenerator {
+    pub fn values(&self) -> Result<Vec<Value>> {
+        match self {
+            Self::Linspace(start, stop, num) => linspace(start, stop, *num),
+            Self::Logspace(start, stop, num) => logspace(start, stop, *num),
+            Self::Range(start, stop, step) => range(start, stop, step),
+        }
+    }
+}
+
+/// Replaces every generator in the substitutions by the list of values it describes.
+pub fn expand_range_generators(
+    substitutions: &HashMap<String, Value>,
+) -> Result<HashMap<String, Value>> {
+    substitutions
+        .iter()
+        .map(|(k, v)| {
+            let value = match v {
+                Value::Mapping(_) => {
+                    let generator: RangeGenerator = serde_yaml::from_value(v.clone())
+                        .with_context(|| format!("Invalid range generator for parameter {}", k))?;
+                    Value::Sequence(
+                        generator
+                            .values()
+                            .with_context(|| format!("While expanding parameter {}", k))?,
+                    )
+                }
+                _ => v.clone(),
+            };
+            Ok((k.clone(), value))
+        })
+        .collect()
+}
+
+fn linspace(start: &Number, stop: &Number, num: usize) -> Result<Vec<Value>> {
+    check_num(num)?;
+    if let (Some(start), Some(stop)) = (start.as_i64(), stop.as_i64()) {
+        let num_intervals = (num as i64 - 1).max(1);
+        if (stop - start) % num_intervals == 0 {
+            let step = (stop - start) / num_intervals;
+            return Ok((0..num as i64)
+                .map(|i| int_value(start + i * step))
+                .collect());
+        }
+    }
+    let (start, stop) = (as_f64(start)?, as_f64(stop)?);
+    Ok(interpolate(start, stop, num, |x| x)
+        .map(float_value)
+        .collect())
+}
+
+fn logspace(start: &Number, stop: &Number, num: usize) -> Result<Vec<Value>> {
+    check_num(num)?;
+    let (start, stop) = (as_f64(start)?, as_f64(stop)?);
+    if start <= 0.0 || stop <= 0.0 {
+        return Err(anyhow!(
+            "Logspace requires positive start and stop values, found {} and {}",
+            start,
+            stop
+        ));
+    }
+    Ok(
+        interpolate(start.log10(), stop.log10(), num, |x| 10.0f64.powf(x))
+            .enumerate()
+            .map(|(i, x)| match i {
+                // Make sure the end points are exactly the values that were given.
+                0 => start,
+                i if i == num - 1 => stop,
+                _ => x,
+            })
+            .map(float_value)
+            .collect(),
+    )
+}
+
+fn range(start: &Number, stop: &Number, step: &Number) -> Result<Vec<Value>> {
+    if let (Some(start), Some(stop), Some(step)) = (start.as_i64(), stop.as_i64(), step.as_i64()) {
+        if step == 0 {
+            return Err(anyhow!("Step size of range is zero"));
+        }
+        let num = ((stop - start) as f64 / step as f64).ceil().max(0.0) as i64;
+        return Ok((0..num).map(|i| int_value(start + i * step)).collect());
+    }
+    let (start, stop, step) = (as_f64(start)?, as_f64(stop)?, as_f64(step)?);
+    if step == 0.0 {
+        return Err(anyhow!("Step size of range is zero"));
+    }
+    let num = ((stop - start) / step).ceil().max(0.0) as usize;
+    Ok((0..num)
+        .map(|i| float_value(start + i as f64 * step))
+        .collect())
+}
+
+fn interpolate(
+    start: f64,
+    stop: f64,
+    num: usize,
+    f: impl Fn(f64) -> f64,
+) -> impl Iterator<Item = f64> {
+    let num_intervals = (num - 1).max(1) as f64;
+    (0..num).map(move |i| match i {
+        0 => f(start),
+        i if i == num - 1 => f(stop),
+        i => f(start + (stop - start) * i as f64 / num_intervals),
+    })
+}
+
+fn check_num(num: usize) -> Result<()> {
+    match num {
+        0 => Err(anyhow!("Number of values to generate is zero")),
+        _ => Ok(()),
+    }
+}
+
+fn as_f64(number: &Number) -> Result<f64> {
+    number
+        .as_f64()
+        .ok_or_else(|| anyhow!("Invalid number in range generator: {}", number))
+}
+
+fn int_value(x: i64) -> Value {
+    Value::Number(Number::from(x))
+}
+
+fn float_value(x: f64) -> Value {
+    // Round away floating point noise such as 0.30000000000000004, so that the
+    // values end up in the parameter files the way one would write them by hand.
+    let rounded: f64 = format!("{:.14e}", x).parse().unwrap();
+    Value::Number(Number::from(rounded))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::to_value;
+
+    use super::*;
+    use crate::param_value::ParamValue;
+
+    fn expand(generator: &str) -> Result<Vec<ParamValue>> {
+        let mut substitutions = HashMap::new();
+        substitutions.insert("a".to_owned(), serde_yaml::from_str(generator)?);
+        let expanded = expand_range_generators(&substitutions)?;
+        match &expanded["a"] {
+            Value::Sequence(s) => s.iter().map(ParamValue::new).collect(),
+            _ => panic!("Generator did not expand to a list"),
+        }
+    }
+
+    #[test]
+    fn linspace_keeps_ints() -> Result<()> {
+        let values = expand("{linspace: [0, 10, 3]}")?;
+        assert_eq!(
+            values,
+            vec![ParamValue::Int(0), ParamValue::Int(5), ParamValue::Int(10)]
+        );
+        let values = expand("{linspace: [0.1, 0.3, 3]}")?;
+        assert_eq!(values[1].to_string(), "0.2");
+        assert_eq!(values[2].to_string(), "0.3");
+        Ok(())
+    }
+
+    #[test]
+    fn logspace() -> Result<()> {
+        let values = expand("{logspace: [1e48, 1e52, 5]}")?;
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[0], ParamValue::new_float(1e48));
+        assert_eq!(values[2], ParamValue::new_float(1e50));
+        assert_eq!(values[4], ParamValue::new_float(1e52));
+        assert!(expand("{logspace: [0, 1, 5]}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn range() -> Result<()> {
+        let values = expand("{range: [1, 7, 2]}")?;
+        assert_eq!(
+            values,
+            vec![ParamValue::Int(1), ParamValue::Int(3), ParamValue::Int(5)]
+        );
+        let values = expand("{range: [0.0, 1.0, 0.25]}")?;
+        assert_eq!(values.len(), 4);
+        assert_eq!(values[3], ParamValue::new_float(0.75));
+        Ok(())
+    }
+
+    #[test]
+    fn plain_values_are_unchanged() -> Result<()> {
+        let mut substitutions = HashMap::new();
+        substitutions.insert("a".to_owned(), to_value([1, 2, 3])?);
+        let expanded = expand_range_generators(&substitutions)?;
+        assert_eq!(expanded["a"], substitutions["a"]);
+        Ok(())
+    }
+}
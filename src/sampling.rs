@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use itertools::Itertools;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_yaml::Number;
+use serde_yaml::Value;
+
+use crate::config::SAMPLE_SEED_IDENTIFIER;
+use crate::param_value::ParamValue;
+use crate::sim_set::get_substitutions_cartesian;
+
+/// The distribution from which the values of a sampled parameter are drawn.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Distribution {
+    /// Uniformly distributed between the two values. If both are integers,
+    /// integers between the two values (inclusive) are drawn.
+    Uniform(Number, Number),
+    /// Uniformly distributed in the logarithm between the two values.
+    LogUniform(f64, f64),
+    /// One of the given values.
+    Choice(Vec<Value>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SampleArgs {
+    num_samples: usize,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    latin_hypercube: bool,
+    distributions: HashMap<String, Distribution>,
+}
+
+impl Distribution {
+    /// Maps a number in [0, 1) to a value of the distribution.
+    fn value_at(&self, u: f64) -> Result<ParamValue> {
+        match self {
+            Self::Uniform(min, max) => match (min.as_i64(), max.as_i64()) {
+                (Some(min), Some(max)) => {
+                    let offset = (u * (max - min + 1) as f64).floor() as i64;
+                    Ok(ParamValue::Int((min + offset).min(max)))
+                }
+                _ => {
+                    let (min, max) = (as_f64(min)?, as_f64(max)?);
+                    Ok(ParamValue::new_float(min + u * (max - min)))
+                }
+            },
+            Self::LogUniform(min, max) => {
+                if *min <= 0.0 || *max <= 0.0 {
+                    return Err(anyhow!(
+                        "Log-uniform distribution requires positive bounds, found {} and {}",
+                        min,
+                        max
+                    ));
+                }
+                let (log_min, log_max) = (min.ln(), max.ln());
+                Ok(ParamValue::new_float(
+                    (log_min + u * (log_max - log_min)).exp(),
+                ))
+            }
+            Self::Choice(values) => {
+                if values.is_empty() {
+                    return Err(anyhow!("Choice between zero values"));
+                }
+                let index = ((u * values.len() as f64).floor() as usize).min(values.len() - 1);
+                ParamValue::new(&values[index])
+            }
+        }
+    }
+}
+
+impl SampleArgs {
+    /// Draws the numbers in [0, 1) that determine the values of one parameter for each sample.
+    /// For latin hypercube sampling, each of the `num_samples` equally sized intervals
+    /// of [0, 1) contains exactly one of the numbers.
+    fn draw_unit_samples(&self, rng: &mut StdRng) -> Vec<f64> {
+        let n = self.num_samples;
+        if self.latin_hypercube {
+            let mut strata: Vec<usize> = (0..n).collect();
+            strata.shuffle(rng);
+            strata
+                .into_iter()
+                .map(|stratum| (stratum as f64 + rng.gen::<f64>()) / n as f64)
+                .collect()
+        } else {
+            (0..n).map(|_| rng.gen::<f64>()).collect()
+        }
+    }
+}
+
+pub fn get_substitutions_sample(
+    substitutions: &HashMap<String, Value>,
+    sample: &SampleArgs,
+) -> Result<Vec<HashMap<String, ParamValue>>> {
+    if let Some(param) = sample
+        .distributions
+        .keys()
+        .find(|param| substitutions.contains_key(*param))
+    {
+        return Err(anyhow!(
+            "Parameter {} is both sampled and given in substitutions",
+            param
+        ));
+    }
+    if sample.num_samples == 0 {
+        return Err(anyhow!("Number of samples is zero"));
+    }
+    let mut sims = get_substitutions_cartesian(substitutions, None)?;
+    let base = match sims.len() {
+        0 => HashMap::new(),
+        1 => sims.remove(0),
+        _ => {
+            return Err(anyhow!(
+                "Substitutions of a sampled sim set may not contain lists"
+            ))
+        }
+    };
+    let mut sims = vec![base; sample.num_samples];
+    let mut rng = StdRng::seed_from_u64(sample.seed);
+    // Iterate in a fixed order so that the drawn values only depend on the seed
+    for (param, distribution) in sample.distributions.iter().sorted_by_key(|(k, _)| *k) {
+        let unit_samples = sample.draw_unit_samples(&mut rng);
+        for (sim, u) in sims.iter_mut().zip(unit_samples) {
+            let value = distribution
+                .value_at(u)
+                .with_context(|| format!("While sampling parameter {}", param))?;
+            sim.insert(param.clone(), value);
+        }
+    }
+    for sim in sims.iter_mut() {
+        sim.insert(
+            SAMPLE_SEED_IDENTIFIER.into(),
+            ParamValue::Int(sample.seed as i64),
+        );
+    }
+    Ok(sims)
+}
+
+fn as_f64(number: &Number) -> Result<f64> {
+    number
+        .as_f64()
+        .ok_or_else(|| anyhow!("Invalid number in distribution: {}", number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_args(latin_hypercube: bool) -> Result<SampleArgs> {
+        Ok(serde_yaml::from_str(&format!(
+            "
+num_samples: 10
+seed: 3
+latin_hypercube: {}
+distributions:
+  a: {{uniform: [0.0, 1.0]}}
+  b: {{log_uniform: [1e48, 1e52]}}
+  c: {{choice: [1, 2, 4]}}
+  d: {{uniform: [1, 3]}}
+",
+            latin_hypercube
+        ))?)
+    }
+
+    #[test]
+    fn sampling_is_reproducible() -> Result<()> {
+        let substitutions = HashMap::new();
+        let sims1 = get_substitutions_sample(&substitutions, &sample_args(false)?)?;
+        let sims2 = get_substitutions_sample(&substitutions, &sample_args(false)?)?;
+        assert_eq!(sims1, sims2);
+        assert_eq!(sims1.len(), 10);
+        for sim in sims1.iter() {
+            assert!((1e48..=1e52).contains(&sim["b"].unwrap_f64()));
+            assert!([1, 2, 4].contains(&sim["c"].unwrap_i64()));
+            assert!((1..=3).contains(&sim["d"].unwrap_i64()));
+            assert_eq!(sim[SAMPLE_SEED_IDENTIFIER], ParamValue::Int(3));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn latin_hypercube_covers_all_strata() -> Result<()> {
+        let sims = get_substitutions_sample(&HashMap::new(), &sample_args(true)?)?;
+        let strata: Vec<usize> = sims
+            .iter()
+            .map(|sim| (sim["a"].unwrap_f64() * 10.0).floor() as usize)
+            .sorted()
+            .collect();
+        assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        Ok(())
+    }
+}
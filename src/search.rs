@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use clap::Clap;
+
+use crate::bisect::bisect_with;
+use crate::bisect::Bisection;
+use crate::config;
+use crate::copy::copy_sim_set;
+use crate::expression::Expr;
+use crate::make::build_sim_set;
+use crate::param_override::ParamOverride;
+use crate::param_value::ParamValue;
+use crate::postprocess::data_plot_info::DataPlotInfo;
+use crate::postprocess::post_fn_name::PostFnName;
+use crate::run::run_sim_set;
+use crate::sim_set::find_bob_file;
+use crate::sim_set::SimSet;
+use crate::sim_set_manifest::SimSetSource;
+use crate::systype::Systype;
+
+/// Find the smallest value of a parameter for which a criterion on the result
+/// of a post function holds, by bisecting the given bracket.
+#[derive(Clap, Debug)]
+pub struct SearchArgs {
+    pub input_folder: Utf8PathBuf,
+    /// Every step of the search is written to a subfolder of this folder
+    pub output_folder: Utf8PathBuf,
+    /// Use the sim set defined in sims.<name>.bob instead of sims.bob
+    #[clap(long)]
+    pub set_file: Option<String>,
+    pub param: String,
+    pub min: f64,
+    pub max: f64,
+    /// Evaluated on the params of the post result and on lastY, minY and maxY
+    /// of its first data set, e.g. 'lastY < 1e-3'
+    #[clap(long)]
+    pub criterion: Expr,
+    /// Stop once the bracket is smaller than this. Integer params stop at a width of 1.
+    #[clap(long)]
+    pub tolerance: Option<f64>,
+    /// The maximum number of steps after evaluating both ends of the bracket
+    #[clap(long, default_value = "10")]
+    pub max_steps: usize,
+    #[clap(long)]
+    pub systype: Option<Systype>,
+    #[clap(short, long)]
+    pub symlink_ics: bool,
+    #[clap(subcommand)]
+    pub function: PostFnName,
+}
+
+/// Returns the smallest value of the parameter found for which the criterion holds.
+/// This assumes that the criterion holds for all values above it.
+pub fn search(args: &SearchArgs, verbose: bool) -> Result<f64> {
+    if config::JOB_FILE_RUN_COMMAND.as_str() != "bash" {
+        return Err(anyhow!(
+            "bob search needs the jobs to run synchronously (job_file_run_command: bash)"
+        ));
+    }
+    let sim_set = SimSet::from_bob_file_and_input_folder(
+        find_bob_file(&args.input_folder, args.set_file.as_deref())?,
+        &args.input_folder,
+    )?;
+    let integer = is_integer_param(&sim_set, &args.param)?;
+    fs::create_dir_all(&args.output_folder)
+        .with_context(|| format!("When creating {}", args.output_folder))?;
+    let mut evaluated: Vec<(f64, bool)> = vec![];
+    let evaluate = |value: f64| -> Result<bool> {
+        if let Some((_, holds)) = evaluated.iter().find(|(v, _)| *v == value) {
+            return Ok(*holds);
+        }
+        let holds = run_step(args, value, integer, verbose)
+            .with_context(|| format!("When evaluating {} = {}", args.param, value))?;
+        println!(
+            "{} = {}: criterion {}",
+            args.param,
+            value,
+            if holds { "holds" } else { "does not hold" }
+        );
+        evaluated.push((value, holds));
+        Ok(holds)
+    };
+    let lowest_holding = search_bracket(
+        evaluate,
+        &args.param,
+        (args.min, args.max),
+        integer,
+        args.tolerance,
+        args.max_steps,
+    )?;
+    println!(
+        "Smallest value of {} for which the criterion holds: {}",
+        args.param, lowest_holding
+    );
+    Ok(lowest_holding)
+}
+
+/// Bisects the bracket with `evaluate`, which tells whether the criterion holds at a value,
+/// and returns the smallest value found for which it holds.
+fn search_bracket(
+    mut evaluate: impl FnMut(f64) -> Result<bool>,
+    param: &str,
+    (min, max): (f64, f64),
+    integer: bool,
+    tolerance: Option<f64>,
+    max_steps: usize,
+) -> Result<f64> {
+    let round = |x: f64| if integer { x.ceil() } else { x };
+    let (min, max) = (round(min), round(max));
+    if !evaluate(max)? {
+        return Err(anyhow!(
+            "Criterion does not hold at the upper end of the bracket ({} = {})",
+            param,
+            max
+        ));
+    }
+    if evaluate(min)? {
+        return Ok(min);
+    }
+    let mut lowest_holding = max;
+    let mut highest_failing = min;
+    let converged = |lowest_holding: f64, highest_failing: f64| match (integer, tolerance) {
+        (true, _) => lowest_holding - highest_failing <= 1.0,
+        (false, Some(tolerance)) => lowest_holding - highest_failing <= tolerance,
+        (false, None) => false,
+    };
+    bisect_with(
+        |x| {
+            if converged(lowest_holding, highest_failing) {
+                return Ok(Bisection::Done);
+            }
+            let value = round(x);
+            if evaluate(value)? {
+                lowest_holding = lowest_holding.min(value);
+                Ok(Bisection::Lower)
+            } else {
+                highest_failing = highest_failing.max(value);
+                Ok(Bisection::Upper)
+            }
+        },
+        min,
+        max,
+        max_steps,
+    )?;
+    Ok(lowest_holding)
+}
+
+fn is_integer_param(sim_set: &SimSet, param: &str) -> Result<bool> {
+    let values: Vec<&ParamValue> = sim_set
+        .iter()
+        .map(|sim| {
+            sim.get(param)
+                .ok_or_else(|| anyhow!("Parameter {} not present in sim {}", param, sim.folder))
+        })
+        .collect::<Result<_>>()?;
+    Ok(values
+        .iter()
+        .all(|value| matches!(value, ParamValue::Int(_))))
+}
+
+/// Builds the sim set of a step from the bob file with the searched parameter
+/// as a substitution, so that derived parameters, templates and folder names
+/// which depend on it follow its value.
+fn get_step_sim_set(
+    args: &SearchArgs,
+    param_override: ParamOverride,
+) -> Result<(SimSet, SimSetSource)> {
+    let mut source = SimSetSource {
+        set_name: args.set_file.clone(),
+        overrides: vec![param_override],
+        ..Default::default()
+    };
+    let sim_set = SimSet::from_input_folder(&args.input_folder, &mut source)?;
+    Ok((sim_set, source))
+}
+
+fn get_step_override(args: &SearchArgs, value: f64, integer: bool) -> Result<ParamOverride> {
+    let value = match integer {
+        true => serde_yaml::to_value(value as i64)?,
+        false => serde_yaml::to_value(value)?,
+    };
+    Ok(ParamOverride {
+        param: args.param.clone(),
+        value,
+    })
+}
+
+fn run_step(args: &SearchArgs, value: f64, integer: bool, verbose: bool) -> Result<bool> {
+    let param_value = match integer {
+        true => ParamValue::Int(value as i64),
+        false => ParamValue::new_float(value),
+    };
+    let param_override = get_step_override(args, value, integer)?;
+    let (sim_set, source) = get_step_sim_set(args, param_override)?;
+    let step_folder = args
+        .output_folder
+        .join(format!("{}_{}", args.param, param_value));
+    let output_sim_set = copy_sim_set(
+        &sim_set,
+        &args.input_folder,
+        &step_folder,
+        &source,
+        true,
+        false,
+        args.symlink_ics,
+    )?;
+    build_sim_set(&output_sim_set, verbose, &args.systype)?;
+    run_sim_set(&output_sim_set, verbose)?;
+    let result = args
+        .function
+        .run(&output_sim_set, None)
+        .next()
+        .ok_or_else(|| anyhow!("Post function returned no results"))??;
+    evaluate_criterion(&args.criterion, &result)
+}
+
+fn evaluate_criterion(criterion: &Expr, result: &DataPlotInfo) -> Result<bool> {
+    let mut values: HashMap<String, ParamValue> = result
+        .replacements
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), v.parse()?)))
+        .collect::<Result<_>>()?;
+    if let Some(data) = result.data.first() {
+        if data.ncols() >= 2 && data.nrows() > 0 {
+            let y = data.column(1);
+            let min_y = y.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max_y = y.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            values.insert("lastY".into(), ParamValue::new_float(y[y.len() - 1]));
+            values.insert("minY".into(), ParamValue::new_float(min_y));
+            values.insert("maxY".into(), ParamValue::new_float(max_y));
+        }
+    }
+    criterion.eval_bool(&mut |name| {
+        values
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown value in search criterion: {}", name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use ndarray::array;
+
+    use super::*;
+    use crate::postprocess::plot_info::PlotInfo;
+    use crate::postprocess::post_expansion::ExpansionFn;
+    use crate::util::write_file;
+
+    /// Searches with a criterion which holds from `threshold` on and
+    /// returns the result and the evaluated values.
+    fn search_threshold(
+        threshold: f64,
+        integer: bool,
+        tolerance: Option<f64>,
+        max_steps: usize,
+    ) -> (Result<f64>, Vec<f64>) {
+        let mut evaluated = vec![];
+        let result = search_bracket(
+            |x| {
+                evaluated.push(x);
+                Ok(x >= threshold)
+            },
+            "a",
+            (1.0, 100.0),
+            integer,
+            tolerance,
+            max_steps,
+        );
+        (result, evaluated)
+    }
+
+    #[test]
+    fn search_integer_param() -> Result<()> {
+        let (result, evaluated) = search_threshold(37.0, true, None, 20);
+        assert_eq!(result?, 37.0);
+        assert!(evaluated.iter().all(|x| x.fract() == 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn search_stops_at_tolerance_or_max_steps() -> Result<()> {
+        let (result, evaluated) = search_threshold(37.3, false, Some(0.5), 20);
+        let result = result?;
+        assert!((37.3..37.8).contains(&result));
+        assert!(evaluated.len() < 22);
+        let (result, evaluated) = search_threshold(37.3, false, None, 3);
+        assert!(result? >= 37.3);
+        assert_eq!(evaluated.len(), 2 + 3);
+        Ok(())
+    }
+
+    #[test]
+    fn search_without_sign_change() -> Result<()> {
+        let (result, evaluated) = search_threshold(-5.0, false, None, 10);
+        assert_eq!(result?, 1.0);
+        assert_eq!(evaluated, [100.0, 1.0]);
+        let (result, evaluated) = search_threshold(500.0, false, None, 10);
+        assert!(result.is_err());
+        assert_eq!(evaluated, [100.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn criterion_on_post_result() -> Result<()> {
+        let result = DataPlotInfo {
+            info: PlotInfo::new("expansion", "expansion", None, None, None),
+            data: vec![array![[0.0, 3.0], [1.0, 0.5]]],
+            replacements: [("stroemgrenRadius".to_string(), "2.0".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        let holds = |criterion: &str| evaluate_criterion(&criterion.parse()?, &result);
+        assert!(holds("lastY < 1")?);
+        assert!(holds("maxY == 3.0 && minY == 0.5")?);
+        assert!(!holds("stroemgrenRadius > 2.0")?);
+        assert!(holds("unknownValue > 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn derived_params_follow_searched_value() -> Result<()> {
+        let dir = TempDir::new("bob_search")?;
+        let input_folder = Utf8PathBuf::from_path_buf(dir.path().to_owned()).unwrap();
+        write_file(
+            &input_folder.join(config::DEFAULT_PARAM_FILE_NAME),
+            "UnitLength_in_cm 1.0\nUnitVelocity_in_cm_per_s 1.0\nUnitMass_in_g 1.0\nTimeLimitCPU 1.0\nTimeMax 1.0\nInitCondFile ics\nNumFilesPerSnapshot 1\n",
+        )?;
+        write_file(&input_folder.join(config::DEFAULT_CONFIG_FILE_NAME), "")?;
+        write_file(
+            &input_folder.join(config::DEFAULT_BOB_CONFIG_NAME),
+            "cartesian_type: All\nsubstitutions:\n  NumFilesPerSnapshot: 5\n  InitCondFile: ics_{NumFilesPerSnapshot}\nderived:\n  TimeMax: NumFilesPerSnapshot * 2\n",
+        )?;
+        let args = SearchArgs {
+            input_folder: input_folder.clone(),
+            output_folder: input_folder.join("search"),
+            set_file: None,
+            param: "NumFilesPerSnapshot".into(),
+            min: 1.0,
+            max: 10.0,
+            criterion: "lastY < 1".parse()?,
+            tolerance: None,
+            max_steps: 10,
+            systype: None,
+            symlink_ics: false,
+            function: PostFnName::Expansion(ExpansionFn {}),
+        };
+        let (sim_set, _) = get_step_sim_set(&args, get_step_override(&args, 7.0, true)?)?;
+        let sim = sim_set.iter().next().unwrap();
+        assert_eq!(sim["NumFilesPerSnapshot"], ParamValue::Int(7));
+        assert_eq!(sim["TimeMax"].unwrap_f64(), 14.0);
+        assert_eq!(sim["InitCondFile"], ParamValue::Str("ics_7".into()));
+        Ok(())
+    }
+}
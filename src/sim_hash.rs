@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use lazy_static::lazy_static;
+
+use crate::arepo_log_file::ArepoLogFile;
+use crate::config;
+use crate::param_value::ParamValue;
+use crate::sim_params::SimParams;
+use crate::sim_set::SimSet;
+use crate::util::get_files_recursively;
+use crate::util::get_git_commit;
+use crate::util::read_file_contents;
+use crate::util::write_file;
+
+lazy_static! {
+    static ref AREPO_COMMIT: String =
+        get_git_commit(&config::AREPO_PATH).unwrap_or_else(|| "unknown".into());
+    /// The checksums of the ICs which were already read. Usually all sims of a set
+    /// share their ICs, so each IC file is only read once unless it changes.
+    static ref IC_CHECKSUMS: Mutex<HashMap<IcsKey, String>> = Mutex::new(HashMap::new());
+}
+
+/// A 64 bit FNV-1a hash, which (unlike the hashers of the standard library)
+/// is guaranteed to give the same result on every machine and rust version.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// Identifies a sim by its parameters and config options, the contents of its
+/// initial conditions and the commit of the Arepo repository it is built from.
+pub fn get_sim_hash(sim: &SimParams) -> Result<String> {
+    let mut hasher = Fnv::new();
+    hasher.write(sim.get_param_file_contents().as_bytes());
+    hasher.write(sim.get_config_file_contents().as_bytes());
+    hash_ics(sim, &mut hasher)?;
+    match sim.get("arepoCommit") {
+        Some(ParamValue::Str(commit)) => hasher.write(commit.as_bytes()),
+        _ => hasher.write(AREPO_COMMIT.as_bytes()),
+    }
+    Ok(hasher.finish())
+}
+
+fn hash_ics(sim: &SimParams, hasher: &mut Fnv) -> Result<()> {
+    // The ICs of later cascade segments do not exist yet
+    if sim.get_default_bool(config::ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER, false)
+        || !matches!(sim.get("InitCondFile"), Some(ParamValue::Str(_)))
+    {
+        return Ok(());
+    }
+    let ics = sim.folder.join(sim.get_ics_filename());
+    let key = get_ics_key(&ics)?;
+    let cached = IC_CHECKSUMS.lock().unwrap().get(&key).cloned();
+    let checksum = match cached {
+        Some(checksum) => checksum,
+        None => {
+            let checksum = get_ics_checksum(&ics)?;
+            IC_CHECKSUMS.lock().unwrap().insert(key, checksum.clone());
+            checksum
+        }
+    };
+    hasher.write(checksum.as_bytes());
+    Ok(())
+}
+
+/// The path, modification time and length of each of the IC files.
+type IcsKey = Vec<(Utf8PathBuf, Option<SystemTime>, u64)>;
+
+fn get_ics_key(ics: &Utf8Path) -> Result<IcsKey> {
+    get_ics_files(ics)?
+        .into_iter()
+        .map(|file| {
+            let metadata = std::fs::metadata(&file)
+                .with_context(|| format!("While hashing ICs at {}", file))?;
+            Ok((file, metadata.modified().ok(), metadata.len()))
+        })
+        .collect()
+}
+
+fn get_ics_files(ics: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut files = match ics.is_dir() {
+        true => get_files_recursively(ics)?,
+        false => vec![ics.to_owned()],
+    };
+    files.sort();
+    Ok(files.into_iter().filter(|file| file.is_file()).collect())
+}
+
+fn get_ics_checksum(ics: &Utf8Path) -> Result<String> {
+    let mut hasher = Fnv::new();
+    let mut buffer = vec![0; 1 << 20];
+    for file in get_ics_files(ics)?.iter() {
+        let mut f = File::open(file).with_context(|| format!("While hashing ICs at {}", file))?;
+        loop {
+            let num_read = f.read(&mut buffer)?;
+            if num_read == 0 {
+                break;
+            }
+            hasher.write(&buffer[..num_read]);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+pub fn write_sim_hash(sim_folder: &Utf8Path, hash: &str) -> Result<()> {
+    write_file(&sim_folder.join(config::DEFAULT_SIM_HASH_FILE_NAME), hash)
+}
+
+pub fn read_sim_hash(sim_folder: &Utf8Path) -> Result<Option<String>> {
+    let path = sim_folder.join(config::DEFAULT_SIM_HASH_FILE_NAME);
+    match path.is_file() {
+        true => Ok(Some(read_file_contents(&path)?.trim().to_owned())),
+        false => Ok(None),
+    }
+}
+
+/// Returns (sim, first sim, hash) for each sim whose hash is the same as that
+/// of an earlier sim. The sims are given as (description, hash).
+fn get_duplicates(sims: &[(String, String)]) -> Vec<(&str, &str, &str)> {
+    let mut first_with_hash: HashMap<&str, &str> = HashMap::new();
+    let mut duplicates = vec![];
+    for (sim, hash) in sims.iter() {
+        match first_with_hash.get(hash.as_str()) {
+            Some(first) => duplicates.push((sim.as_str(), *first, hash.as_str())),
+            None => {
+                first_with_hash.insert(hash, sim);
+            }
+        }
+    }
+    duplicates
+}
+
+/// Prints a warning for each sim whose hash is the same as that of an earlier sim.
+/// The sims are given as (description, hash).
+pub fn warn_about_duplicates(sims: &[(String, String)]) {
+    for (sim, first, hash) in get_duplicates(sims) {
+        eprintln!("Warning: {} is identical to {} (hash {})", sim, first, hash);
+    }
+}
+
+/// Warns about sims with identical hashes, using the hashes stored in the sim folders.
+pub fn warn_about_duplicates_in_output(sim_set: &SimSet) {
+    let sims: Vec<(String, String)> = sim_set
+        .iter()
+        .filter_map(|sim| {
+            let hash = read_sim_hash(&sim.folder).ok()??;
+            Some((sim.folder.to_string(), hash))
+        })
+        .collect();
+    warn_about_duplicates(&sims);
+}
+
+/// Looks through the output roots for finished runs with the same hash as each
+/// sim in the sim set and returns their folders.
+pub fn find_existing_runs(
+    sim_set: &SimSet,
+    output_roots: &[Utf8PathBuf],
+) -> Result<Vec<(usize, Vec<Utf8PathBuf>)>> {
+    let mut folders_by_hash: HashMap<String, Vec<Utf8PathBuf>> = HashMap::new();
+    for root in output_roots.iter() {
+        let hash_files = get_files_recursively(root)
+            .with_context(|| format!("While searching output root {}", root))?
+            .into_iter()
+            .filter(|file| file.file_name() == Some(config::DEFAULT_SIM_HASH_FILE_NAME));
+        for hash_file in hash_files {
+            let folder = hash_file.parent().unwrap().to_owned();
+            if !ArepoLogFile::new(&folder.join(config::DEFAULT_LOG_FILE)).run_finished() {
+                continue;
+            }
+            if let Some(hash) = read_sim_hash(&folder)? {
+                folders_by_hash.entry(hash).or_default().push(folder);
+            }
+        }
+    }
+    sim_set
+        .enumerate()
+        .map(|(i, sim)| {
+            let hash = get_sim_hash(sim)?;
+            let mut runs = folders_by_hash.get(&hash).cloned().unwrap_or_default();
+            runs.sort();
+            Ok((*i, runs))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::sim_params::SimParamsKind;
+    use crate::test_utils::sim_params_in;
+
+    fn sim(folder: &Utf8Path, time_max: i64) -> SimParams {
+        sim_params_in(
+            folder,
+            SimParamsKind::Input,
+            &[
+                ("TimeMax", ParamValue::Int(time_max)),
+                ("InitCondFile", ParamValue::Str("ics".into())),
+                ("ICFormat", ParamValue::Int(1)),
+                ("arepoCommit", ParamValue::Str("commit".into())),
+            ],
+        )
+    }
+
+    fn write_run(folder: &Utf8Path, hash: &str, finished: bool) -> Result<()> {
+        std::fs::create_dir_all(folder)?;
+        write_sim_hash(folder, hash)?;
+        let log = match finished {
+            true => "Simulation ends",
+            false => "",
+        };
+        write_file(&folder.join(config::DEFAULT_LOG_FILE), log)
+    }
+
+    #[test]
+    fn fnv_is_stable() {
+        let mut hasher = Fnv::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn duplicates() {
+        let sims: Vec<(String, String)> = [("0", "a"), ("1", "b"), ("2", "a"), ("3", "a")]
+            .iter()
+            .map(|(sim, hash)| (sim.to_string(), hash.to_string()))
+            .collect();
+        assert_eq!(get_duplicates(&sims), [("2", "0", "a"), ("3", "0", "a")]);
+    }
+
+    #[test]
+    fn changed_ics_change_the_hash() -> Result<()> {
+        let dir = TempDir::new("bob_sim_hash_ics")?;
+        let folder = Utf8Path::from_path(dir.path()).unwrap();
+        write_file(&folder.join("ics"), "first")?;
+        let hash = get_sim_hash(&sim(folder, 1))?;
+        assert_eq!(get_sim_hash(&sim(folder, 1))?, hash);
+        assert_ne!(get_sim_hash(&sim(folder, 2))?, hash);
+        write_file(&folder.join("ics"), "second file")?;
+        assert_ne!(get_sim_hash(&sim(folder, 1))?, hash);
+        Ok(())
+    }
+
+    #[test]
+    fn existing_runs_are_finished_runs() -> Result<()> {
+        let dir = TempDir::new("bob_sim_hash_runs")?;
+        let root = Utf8Path::from_path(dir.path()).unwrap();
+        write_file(&root.join("ics"), "ics")?;
+        let sims = vec![sim(root, 1), sim(root, 2), sim(root, 3)];
+        write_run(&root.join("a/0"), &get_sim_hash(&sims[0])?, true)?;
+        write_run(&root.join("a/1"), &get_sim_hash(&sims[1])?, false)?;
+        write_run(&root.join("b/0"), &get_sim_hash(&sims[0])?, true)?;
+        let sim_set: SimSet = sims.into_iter().enumerate().collect();
+        let runs = find_existing_runs(&sim_set, &[root.join("a"), root.join("b")])?;
+        assert_eq!(
+            runs,
+            [
+                (0, vec![root.join("a/0"), root.join("b/0")]),
+                (1, vec![]),
+                (2, vec![])
+            ]
+        );
+        Ok(())
+    }
+}
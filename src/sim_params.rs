@@ -21,16 +21,21 @@ use uom::si::mass::gram;
 use uom::si::time::second;
 use uom::si::velocity::centimeter_per_second;
 
+use crate::arepo_config_file::ArepoConfigFile;
 use crate::arepo_log_file::ArepoLogFile;
+use crate::arepo_options;
 use crate::config;
 use crate::job_params::JobParams;
+use crate::param_file::ParamFile;
 use crate::param_value::ParamValue;
+use crate::quantity::Quantity;
 use crate::sim_units::SimUnits;
 use crate::simplex_log_file::SimplexLogFile;
 use crate::strfmt_utils::strfmt_anyhow;
 use crate::util::copy_file;
 use crate::util::read_file_contents;
 use crate::util::write_file;
+use crate::yaml_include::read_yaml_with_includes;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SimParamsKind {
@@ -58,9 +63,9 @@ impl From<&str> for ParamType {
     fn from(value: &str) -> Self {
         if config::SPECIAL_PARAMS.contains(&value) {
             Self::Special
-        } else if config::CONFIG_FILE_PARAMS.contains(&value) {
+        } else if arepo_options::is_config_option(value) {
             Self::Config
-        } else if config::PARAM_FILE_PARAMS.contains(&value) {
+        } else if arepo_options::is_param(value) {
             Self::Param
         } else if value.chars().all(|c| c.is_uppercase() || c == '_') {
             Self::Config
@@ -70,11 +75,11 @@ impl From<&str> for ParamType {
     }
 }
 
-fn is_config_file_param(s: &str) -> bool {
+pub(crate) fn is_config_file_param(s: &str) -> bool {
     ParamType::from(s) == ParamType::Config
 }
 
-fn is_param_file_param(s: &str) -> bool {
+pub(crate) fn is_param_file_param(s: &str) -> bool {
     ParamType::from(s) == ParamType::Param
 }
 
@@ -150,21 +155,62 @@ impl SimParams {
         params: HashMap<String, ParamValue>,
         kind: SimParamsKind,
     ) -> Result<SimParams> {
-        let get_f64 = |k| try_get_f64(&params, k);
-        let units = SimUnits::new(
-            Length::new::<centimeter>(get_f64("UnitLength_in_cm")?),
-            Velocity::new::<centimeter_per_second>(get_f64("UnitVelocity_in_cm_per_s")?),
-            Mass::new::<gram>(get_f64("UnitMass_in_g")?),
-        );
+        let units = get_units(&params)?;
         Ok(SimParams {
             folder: folder.to_owned(),
-            time_limit_cpu: Time::new::<second>(get_f64("TimeLimitCPU")?),
+            time_limit_cpu: Time::new::<second>(try_get_f64(&params, "TimeLimitCPU")?),
             units,
             params,
             kind,
         })
     }
 
+    /// Converts the values of Arepo parameters given with a unit (such as "13.2 kpc")
+    /// to code units. Arepo reads parameter values as single words, so these can not
+    /// be string values. Other parameters and values with unknown units stay strings.
+    /// The units are determined from the current parameters first, since they
+    /// may have been changed by substitutions.
+    pub fn convert_quantities(&mut self) -> Result<()> {
+        self.units = get_units(&self.params)?;
+        for (key, value) in self.params.iter_mut() {
+            if config::SPECIAL_PARAMS.contains(&key.as_str()) || !arepo_options::is_param(key) {
+                continue;
+            }
+            if let ParamValue::Str(s) = value {
+                if let Some(quantity) = Quantity::parse_if_quantity(s) {
+                    *value =
+                        ParamValue::Quantity(quantity.to_code_units(&self.units).into(), s.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces placeholders such as "ics_res{ResolutionLevel}" in string parameters
+    /// by the values of the named parameters. Placeholders can not refer to other
+    /// parameters containing placeholders.
+    pub fn resolve_templates(&mut self) -> Result<()> {
+        let is_template = |key: &str, value: &ParamValue| {
+            !config::SPECIAL_PARAMS.contains(&key)
+                && matches!(value, ParamValue::Str(s) if s.contains('{'))
+        };
+        let replacements: HashMap<String, String> = self
+            .params
+            .iter()
+            .filter(|(k, v)| !is_template(k, v))
+            .map(|(k, v)| (k.clone(), v.to_string()))
+            .collect();
+        for (key, value) in self.params.iter_mut() {
+            if !is_template(key, value) {
+                continue;
+            }
+            let resolved = strfmt_anyhow(value.unwrap_string(), replacements.clone())
+                .with_context(|| format!("While resolving placeholders in parameter {}", key))?;
+            *value = ParamValue::Str(resolved);
+        }
+        Ok(())
+    }
+
     pub fn get_name(&self) -> String {
         self.folder.file_name().unwrap().to_owned()
     }
@@ -177,13 +223,37 @@ impl SimParams {
         self.params.contains_key(key)
     }
 
+    /// Writes the param file, keeping the order and comments of the param
+    /// file in the folder of the sim if there is one.
     pub fn write_param_file(&self, path: &Utf8Path) -> Result<()> {
-        let contents = self.get_param_file_contents();
+        let original_path = get_param_file_path(&self.folder);
+        let contents = match original_path.is_file() {
+            true => {
+                self.get_param_file_contents_from_original(&read_file_contents(&original_path)?)
+            }
+            false => self.get_param_file_contents(),
+        };
         write_file(path, &contents)?;
         Ok(())
     }
 
-    fn get_param_file_contents(&self) -> String {
+    fn get_param_file_contents_from_original(&self, original: &str) -> String {
+        let mut param_file = ParamFile::parse(original);
+        let mut sorted_keys: Vec<&String> = self.keys().collect();
+        sorted_keys.sort();
+        for key in sorted_keys
+            .into_iter()
+            .filter(|key| is_param_file_param(key))
+        {
+            match self[key].as_option() {
+                Some(value) => param_file.set(key, value),
+                None => param_file.remove(key),
+            }
+        }
+        param_file.to_string()
+    }
+
+    pub(crate) fn get_param_file_contents(&self) -> String {
         let mut sorted_keys: Vec<&String> = self.keys().collect();
         sorted_keys.sort();
         sorted_keys
@@ -197,13 +267,36 @@ impl SimParams {
             .join("\n")
     }
 
+    /// Writes the Config.sh, changing the options of the Config.sh in the
+    /// folder of the sim in place if there is one.
     pub fn write_config_file(&self, path: &Utf8Path) -> Result<()> {
-        let contents = self.get_config_file_contents();
+        let original_path = get_config_file_path(&self.folder);
+        let contents = match original_path.is_file() {
+            true => {
+                self.get_config_file_contents_from_original(&read_file_contents(&original_path)?)
+            }
+            false => self.get_config_file_contents(),
+        };
         write_file(path, &contents)?;
         Ok(())
     }
 
-    fn get_config_file_contents(&self) -> String {
+    fn get_config_file_contents_from_original(&self, original: &str) -> String {
+        let mut config_file = ArepoConfigFile::parse(original);
+        let mut sorted_keys: Vec<&String> = self.keys().collect();
+        sorted_keys.sort();
+        for key in sorted_keys
+            .into_iter()
+            .filter(|key| is_config_file_param(key))
+        {
+            if let Some(value) = self[key].as_option() {
+                config_file.set(key, value);
+            }
+        }
+        config_file.to_string()
+    }
+
+    pub(crate) fn get_config_file_contents(&self) -> String {
         let mut sorted_keys: Vec<&String> = self.keys().collect();
         sorted_keys.sort();
         sorted_keys
@@ -216,7 +309,9 @@ impl SimParams {
                 },
                 ParamValue::Int(value) => Some(format!("{}={}", key, value)),
                 ParamValue::Float(_, s) => Some(format!("{}={}", key, s)),
+                ParamValue::Quantity(..) => Some(format!("{}={}", key, self[key])),
                 ParamValue::Str(s) => Some(format!("{}={}", key, s)),
+                ParamValue::List(_) => Some(format!("{}={}", key, self[key])),
                 _ => panic!("Wrong param value: {}", key),
             })
             .flatten()
@@ -258,6 +353,10 @@ impl SimParams {
 
     pub fn copy_ics(&self, target_folder: &Utf8Path, symlink_ics: bool) -> Result<()> {
         let sim_output_folder = get_output_folder_from_sim_folder(self, target_folder);
+        // The ICs of later cascade segments are written by the previous segment
+        if self.get_default_bool(config::ICS_FROM_PREVIOUS_SEGMENT_IDENTIFIER, false) {
+            return Ok(());
+        }
         let ics_file_name = self.get_ics_filename();
         // Nothing to do if the ICS are given as an absolute path
         if ics_file_name.is_absolute() {
@@ -446,6 +545,15 @@ pub fn get_job_file_path<U: AsRef<Utf8Path>>(folder: U) -> Utf8PathBuf {
     folder.as_ref().join(config::DEFAULT_JOB_FILE_NAME)
 }
 
+fn get_units(params: &HashMap<String, ParamValue>) -> Result<SimUnits> {
+    let get_f64 = |k| try_get_f64(params, k);
+    Ok(SimUnits::new(
+        Length::new::<centimeter>(get_f64("UnitLength_in_cm")?),
+        Velocity::new::<centimeter_per_second>(get_f64("UnitVelocity_in_cm_per_s")?),
+        Mass::new::<gram>(get_f64("UnitMass_in_g")?),
+    ))
+}
+
 pub fn try_get_f64(map: &HashMap<String, ParamValue>, key: &str) -> Result<f64> {
     map.get(key)
         .map(|v| v.unwrap_f64())
@@ -462,6 +570,19 @@ fn update_from(
     new_params: HashMap<String, ParamValue>,
 ) -> Result<()> {
     for (key, value) in new_params.into_iter() {
+        // Keep the original string of values that were given with a unit,
+        // if the number in the parameter file agrees with it.
+        if let Some(ParamValue::Quantity(x, _)) = params.get(&key) {
+            if matches!(value, ParamValue::Float(y, _) if y == *x) {
+                continue;
+            }
+        }
+        // Lists are written to the parameter file as text, keep the typed value.
+        if let Some(list @ ParamValue::List(_)) = params.get(&key) {
+            if matches!(&value, ParamValue::Str(s) if *s == list.to_string()) {
+                continue;
+            }
+        }
         if let Some(previous_value) = params.insert(key.clone(), value.clone()) {
             if previous_value != value {
                 eprintln!(
@@ -486,9 +607,6 @@ fn read_config_file(path: &Utf8Path) -> Result<HashMap<String, ParamValue>> {
 
 fn read_config_lines(content: &str, comment_string: &str) -> Result<HashMap<String, ParamValue>> {
     let mut params = HashMap::new();
-    for param in config::CONFIG_FILE_PARAMS {
-        params.insert(param.to_string(), ParamValue::Bool(false));
-    }
     let mut invalid_keys = vec![];
     for line in get_nonempty_noncomment_lines(content, comment_string) {
         let (mut key, value) = match line.contains(&"=") {
@@ -505,9 +623,10 @@ fn read_config_lines(content: &str, comment_string: &str) -> Result<HashMap<Stri
             false => Ok((line.to_string(), ParamValue::Bool(true))),
         }?;
         key = key.trim_start().trim_end().to_string();
-        if params.insert(key.clone(), value) == None {
-            invalid_keys.push(key);
+        if !arepo_options::is_config_option(&key) {
+            invalid_keys.push(key.clone());
         }
+        params.insert(key, value);
     }
     if !invalid_keys.is_empty() {
         println!(
@@ -574,9 +693,9 @@ fn read_parameter_lines(
         .collect()
 }
 
-fn read_bob_param_file(path: &Utf8Path) -> Result<HashMap<String, ParamValue>> {
-    let contents = read_file_contents(path)?;
-    serde_yaml::from_str(&contents).context("While reading plot info file")
+pub fn read_bob_param_file(path: &Utf8Path) -> Result<HashMap<String, ParamValue>> {
+    let value = read_yaml_with_includes(path, &[])?;
+    serde_yaml::from_value(value).context("While reading bob parameter file")
 }
 
 impl Index<&str> for SimParams {
@@ -588,3 +707,70 @@ impl Index<&str> for SimParams {
             .expect(&format!("Key not found in sim: {}", key))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sim_params_with;
+
+    #[test]
+    fn resolve_templates() -> Result<()> {
+        let mut sim = sim_params_with(&[
+            ("ResolutionLevel", ParamValue::Int(7)),
+            (
+                "InitCondFile",
+                ParamValue::Str("ics_res{ResolutionLevel}".into()),
+            ),
+            ("OutputDir", ParamValue::Str("output_{InitCondFile}".into())),
+        ]);
+        assert!(sim.resolve_templates().is_err());
+        sim.insert("OutputDir", &ParamValue::Str("output".into()));
+        sim.resolve_templates()?;
+        assert_eq!(sim["InitCondFile"], ParamValue::Str("ics_res7".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn only_arepo_params_are_converted_to_quantities() -> Result<()> {
+        let mut sim = sim_params_with(&[
+            ("UnitLength_in_cm", ParamValue::new_float(3.085678e21)),
+            ("UnitVelocity_in_cm_per_s", ParamValue::new_float(1e5)),
+            ("UnitMass_in_g", ParamValue::new_float(1.989e43)),
+            ("BoxSize", ParamValue::Str("2 kpc".into())),
+            ("OutputDir", ParamValue::Str("3 apples".into())),
+            ("description", ParamValue::Str("5 kpc".into())),
+        ]);
+        sim.convert_quantities()?;
+        assert!(matches!(sim["BoxSize"], ParamValue::Quantity(x, _) if (*x - 2.0).abs() < 1e-10));
+        assert_eq!(sim["OutputDir"], ParamValue::Str("3 apples".into()));
+        assert_eq!(sim["description"], ParamValue::Str("5 kpc".into()));
+        assert_eq!(
+            ParamValue::Quantity(13.2.into(), "13.2 kpc".into()).to_string(),
+            "13.2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn only_present_config_options_are_read() -> Result<()> {
+        let params = read_config_lines("SWEEP\n# TWODIMS\nSWEEP_SOURCES=10\n", "#")?;
+        assert_eq!(params.len(), 2);
+        assert_eq!(params["SWEEP"], ParamValue::Bool(true));
+        assert_eq!(params["SWEEP_SOURCES"], ParamValue::Int(10));
+        Ok(())
+    }
+
+    #[test]
+    fn unset_params_are_removed_from_original() -> Result<()> {
+        let sim = sim_params_with(&[
+            ("InitCondFile", ParamValue::Str("ics".into())),
+            ("TimeMax", ParamValue::None),
+        ]);
+        let contents = sim.get_param_file_contents_from_original(
+            "InitCondFile    ics\nTimeMax         1.0\nOutputDir       output\n",
+        );
+        assert!(contents.starts_with("InitCondFile    ics\nOutputDir       output\n"));
+        assert!(!contents.contains("TimeMax"));
+        Ok(())
+    }
+}
@@ -1,6 +1,5 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fs;
 use std::iter::FromIterator;
 use std::slice::Iter;
 
@@ -14,36 +13,146 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_yaml::Value;
 
+use crate::bob_file_check::check_bob_file;
+use crate::bob_file_check::problems_to_result;
 use crate::config;
+use crate::derived_params::DerivedParams;
+use crate::expression::Expr;
 use crate::job_cascade::get_substitutions_cascade;
 use crate::job_cascade::CascadeArgs;
+use crate::param_override::ParamOverride;
 use crate::param_value::ParamValue;
+use crate::range_generator::expand_range_generators;
+use crate::sampling::get_substitutions_sample;
+use crate::sampling::SampleArgs;
 use crate::sim_params::SimParams;
 use crate::sim_params::SimParamsKind;
+use crate::sim_set_manifest::SimSetManifest;
+use crate::sim_set_manifest::SimSetSource;
+use crate::substitution_filter::apply_filters;
+use crate::substitution_filter::FilterReport;
+use crate::substitution_filter::SubstitutionFilter;
 use crate::util::get_common_path;
+use crate::util::get_files;
 use crate::util::get_folders;
+use crate::util::read_file_contents;
+use crate::yaml_include::read_yaml_with_includes;
+
+/// The keys of a bob file whose mappings are merged entry by entry with included files
+pub(crate) static BOB_FILE_MERGED_KEYS: &[&str] = &["substitutions", "derived"];
 
 #[derive(Serialize, Deserialize)]
-enum CartesianType {
+pub(crate) enum CartesianType {
     NoCartesian,
     All,
     Grouped(Vec<Vec<String>>),
     Cascade(CascadeArgs),
+    Sample(SampleArgs),
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SimSetConfig {
     cartesian_type: CartesianType,
     substitutions: HashMap<String, Value>,
+    #[serde(default)]
+    derived: HashMap<String, String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    include_only: Vec<String>,
+    #[serde(default)]
+    folder_name: Option<String>,
+    /// The number of copies of each sim, which differ only in `seed_param`
+    #[serde(default)]
+    replicas: Option<usize>,
+    #[serde(default)]
+    seed_param: Option<String>,
 }
 
 impl SimSetConfig {
     pub fn from_file<U: AsRef<Utf8Path>>(path: U) -> Result<SimSetConfig> {
-        let data = fs::read_to_string(path.as_ref()).context(format!(
-            "While reading bob config file at {:?}",
-            path.as_ref()
-        ))?;
-        serde_yaml::from_str(&data).context("Reading bob config file contents")
+        let path = path.as_ref();
+        let problems = check_bob_file(path)
+            .with_context(|| format!("While reading bob config file at {:?}", path))?;
+        problems_to_result(path, &problems)?;
+        let value = read_yaml_with_includes(path, BOB_FILE_MERGED_KEYS)?;
+        serde_yaml::from_value(value).context("Reading bob config file contents")
+    }
+
+    /// Layers substitutions given on the command line over the ones in the bob file.
+    /// With `cartesian`, each overridden parameter varies independently of all others.
+    /// Without it, an overridden parameter of a Grouped sim set varies together with its
+    /// group, and a list of values for a parameter which is in no group is an error.
+    pub fn apply_overrides(&mut self, overrides: &[ParamOverride], cartesian: bool) -> Result<()> {
+        if cartesian
+            && matches!(
+                self.cartesian_type,
+                CartesianType::Cascade(_) | CartesianType::Sample(_)
+            )
+        {
+            return Err(anyhow!(
+                "--cartesian cannot be used with {} sim sets",
+                self.cartesian_type_name()
+            ));
+        }
+        let params: Vec<String> = overrides.iter().map(|o| o.param.clone()).collect();
+        let cartesian_type =
+            std::mem::replace(&mut self.cartesian_type, CartesianType::NoCartesian);
+        self.cartesian_type = match cartesian_type {
+            CartesianType::NoCartesian if cartesian => {
+                let rest: Vec<String> = self
+                    .substitutions
+                    .keys()
+                    .filter(|k| !params.contains(k))
+                    .cloned()
+                    .collect();
+                let groups = Some(rest).filter(|rest| !rest.is_empty()).into_iter();
+                CartesianType::Grouped(
+                    groups
+                        .chain(params.iter().map(|p| vec![p.clone()]))
+                        .collect(),
+                )
+            }
+            CartesianType::Grouped(groups) => {
+                let mut groups: Vec<Vec<String>> = match cartesian {
+                    true => groups
+                        .into_iter()
+                        .map(|group| group.into_iter().filter(|k| !params.contains(k)).collect())
+                        .filter(|group: &Vec<String>| !group.is_empty())
+                        .collect(),
+                    false => groups,
+                };
+                for o in overrides.iter() {
+                    if groups.iter().any(|group| group.contains(&o.param)) {
+                        continue;
+                    }
+                    if !cartesian && matches!(o.value, Value::Sequence(_)) {
+                        return Err(anyhow!(
+                            "Overridden parameter {} is not in any parameter group, use --cartesian to vary it independently",
+                            o.param
+                        ));
+                    }
+                    groups.push(vec![o.param.clone()]);
+                }
+                CartesianType::Grouped(groups)
+            }
+            cartesian_type => cartesian_type,
+        };
+        for o in overrides.iter() {
+            self.derived.remove(&o.param);
+            self.substitutions.insert(o.param.clone(), o.value.clone());
+        }
+        Ok(())
+    }
+
+    pub fn cartesian_type_name(&self) -> &str {
+        match self.cartesian_type {
+            CartesianType::NoCartesian => "NoCartesian",
+            CartesianType::All => "All",
+            CartesianType::Grouped(_) => "Grouped",
+            CartesianType::Cascade(_) => "Cascade",
+            CartesianType::Sample(_) => "Sample",
+        }
     }
 }
 
@@ -73,16 +182,71 @@ impl SimSet {
         config_file_path: U,
         folder: V,
     ) -> Result<SimSet> {
-        let config = SimSetConfig::from_file(config_file_path)?;
-        let simulations = get_sim_params(
+        Self::from_bob_file_and_input_folder_with_filter_reports(config_file_path, folder)
+            .map(|(sim_set, _)| sim_set)
+    }
+
+    /// Like `from_bob_file_and_input_folder` but also returns how many parameter
+    /// combinations were removed by each of the exclude/include_only rules.
+    pub fn from_bob_file_and_input_folder_with_filter_reports<
+        U: AsRef<Utf8Path>,
+        V: AsRef<Utf8Path>,
+    >(
+        config_file_path: U,
+        folder: V,
+    ) -> Result<(SimSet, Vec<FilterReport>)> {
+        Self::from_bob_file_and_input_folder_with_overrides(config_file_path, folder, &[], false)
+    }
+
+    /// Like `from_bob_file_and_input_folder_with_filter_reports` but with
+    /// substitutions from the command line layered over the bob file.
+    pub fn from_bob_file_and_input_folder_with_overrides<U: AsRef<Utf8Path>, V: AsRef<Utf8Path>>(
+        config_file_path: U,
+        folder: V,
+        overrides: &[ParamOverride],
+        cartesian: bool,
+    ) -> Result<(SimSet, Vec<FilterReport>)> {
+        let mut config = SimSetConfig::from_file(config_file_path)?;
+        config.apply_overrides(overrides, cartesian)?;
+        get_sim_params(
             folder.as_ref(),
             &config,
             SimParams::from_folder(folder.as_ref(), SimParamsKind::Input)?,
-        )?;
-        Ok(SimSet { simulations })
+        )
+    }
+
+    /// Reads the sim set defined by the bob file of the source in the input folder, with
+    /// the overrides of the source layered over it. Records the bob file contents and
+    /// the cartesian type in the source, so that the manifest describes the same sims.
+    pub fn from_input_folder(folder: &Utf8Path, source: &mut SimSetSource) -> Result<SimSet> {
+        let config_file_path = find_bob_file(folder, source.set_name.as_deref())?;
+        let contents = read_file_contents(&config_file_path)?;
+        let mut config = SimSetConfig::from_file(&config_file_path)?;
+        source.bob_file = Some(contents);
+        source.cartesian_type = Some(config.cartesian_type_name().to_owned());
+        config.apply_overrides(&source.overrides, source.cartesian)?;
+        get_sim_params(
+            folder,
+            &config,
+            SimParams::from_folder(folder, SimParamsKind::Input)?,
+        )
+        .map(|(sim_set, _)| sim_set)
     }
 
     pub fn from_output_folder<U: AsRef<Utf8Path>>(folder: U) -> Result<SimSet> {
+        if let Some(manifest) = SimSetManifest::read(folder.as_ref())? {
+            return manifest
+                .sims
+                .iter()
+                .map(|entry| -> Result<(usize, SimParams)> {
+                    let sim_folder = folder.as_ref().join(&entry.name);
+                    Ok((
+                        entry.index,
+                        SimParams::from_folder(sim_folder, SimParamsKind::Output)?,
+                    ))
+                })
+                .collect();
+        }
         let all_folders = get_folders(folder.as_ref())?;
         let mut sim_folders: Vec<(usize, &Utf8PathBuf)> = all_folders
             .iter()
@@ -161,6 +325,33 @@ impl SimSet {
         sub_sim_sets
     }
 
+    /// Whether the sims were generated as replicas (see the `replicas` option of the bob file).
+    pub fn has_replicas(&self) -> bool {
+        self.iter()
+            .any(|sim| sim.contains_key(config::REPLICA_IDENTIFIER))
+    }
+
+    /// Splits the sim set into ensembles of replicas, which agree in all
+    /// parameters except for the replica number and the seed parameter.
+    pub fn ensembles(&self) -> Vec<SimSet> {
+        let replica_params: Vec<&str> = self
+            .iter()
+            .filter_map(|sim| sim.get(config::REPLICA_SEED_PARAM_IDENTIFIER))
+            .map(|seed_param| seed_param.unwrap_string())
+            .chain([config::REPLICA_IDENTIFIER])
+            .collect();
+        let params: Vec<&str> = self
+            .iter()
+            .flat_map(|sim| sim.keys())
+            .unique()
+            .filter(|param| !replica_params.contains(&param.as_str()) && self.varies(param))
+            .filter(|param| self.iter().all(|sim| sim.contains_key(param)))
+            .map(|param| param.as_str())
+            .sorted()
+            .collect();
+        self.quotients(&params)
+    }
+
     pub fn quotients<'a>(&'a self, params: &[&str]) -> Vec<SimSet> {
         let next_param = params.first();
         match next_param {
@@ -178,23 +369,109 @@ fn get_sim_params(
     folder: &Utf8Path,
     config: &SimSetConfig,
     base_sim_params: SimParams,
-) -> Result<Vec<(usize, SimParams)>> {
-    let substitutions = match &config.cartesian_type {
-        CartesianType::NoCartesian => get_substitutions_normal(&config.substitutions),
-        CartesianType::All => get_substitutions_cartesian(&config.substitutions, None),
+) -> Result<(SimSet, Vec<FilterReport>)> {
+    let expanded_substitutions = expand_range_generators(&config.substitutions)?;
+    let mut substitutions = match &config.cartesian_type {
+        CartesianType::NoCartesian => get_substitutions_normal(&expanded_substitutions),
+        CartesianType::All => get_substitutions_cartesian(&expanded_substitutions, None),
         CartesianType::Grouped(l) => {
-            get_substitutions_cartesian(&config.substitutions, Some(l.to_vec()))
+            get_substitutions_cartesian(&expanded_substitutions, Some(l.to_vec()))
         }
         CartesianType::Cascade(l) => {
-            get_substitutions_cascade(&base_sim_params, folder, &config.substitutions, l)
+            get_substitutions_cascade(&base_sim_params, folder, &expanded_substitutions, l)
         }
+        CartesianType::Sample(l) => get_substitutions_sample(&expanded_substitutions, l),
     }?;
-    get_sim_params_from_substitutions(base_sim_params, substitutions)
+    let filters = SubstitutionFilter::from_rules(&config.exclude, &config.include_only)?;
+    if !filters.is_empty() && matches!(config.cartesian_type, CartesianType::Cascade(_)) {
+        return Err(anyhow!(
+            "Exclude/include_only rules cannot be used with cascade sim sets"
+        ));
+    }
+    let filter_reports = apply_filters(&filters, &mut substitutions, &base_sim_params)?;
+    if let Some(folder_name) = &config.folder_name {
+        for substitution_map in substitutions.iter_mut() {
+            let previous = substitution_map.insert(
+                config::FOLDER_NAME_IDENTIFIER.into(),
+                ParamValue::Str(folder_name.clone()),
+            );
+            if previous.is_some() {
+                return Err(anyhow!(
+                    "Folder name given both as folder_name and in substitutions"
+                ));
+            }
+        }
+    }
+    if let Some(replicas) = config.replicas {
+        substitutions = get_substitutions_replicas(
+            substitutions,
+            replicas,
+            config.seed_param.as_deref(),
+            &base_sim_params,
+        )?;
+    }
+    let derived = DerivedParams::new(&config.derived)?;
+    if let Some(k) = config.substitutions.keys().find(|k| derived.contains(k)) {
+        return Err(anyhow!(
+            "Parameter {} appears both in substitutions and derived parameters",
+            k
+        ));
+    }
+    let simulations = get_sim_params_from_substitutions(base_sim_params, substitutions, &derived)?;
+    Ok((SimSet { simulations }, filter_reports))
+}
+
+/// Replaces each parameter combination by `replicas` copies which only differ in
+/// the replica number and (if given) the seed parameter, which is offset by the
+/// replica number from its value in the base sim.
+fn get_substitutions_replicas(
+    substitutions: Vec<HashMap<String, ParamValue>>,
+    replicas: usize,
+    seed_param: Option<&str>,
+    base: &SimParams,
+) -> Result<Vec<HashMap<String, ParamValue>>> {
+    let base_seed = match seed_param {
+        Some(seed_param) => match base.get(seed_param) {
+            Some(ParamValue::Int(seed)) => Some((seed_param, *seed)),
+            Some(value) => {
+                return Err(anyhow!(
+                    "Seed parameter {} is not an integer: {}",
+                    seed_param,
+                    value
+                ))
+            }
+            None => return Err(anyhow!("Seed parameter {} not found", seed_param)),
+        },
+        None => None,
+    };
+    let mut result = vec![];
+    for substitution_map in substitutions.into_iter() {
+        for replica in 0..replicas {
+            let mut replica_map = substitution_map.clone();
+            let mut insert = |k: &str, v| {
+                if replica_map.insert(k.into(), v).is_some() {
+                    return Err(anyhow!("Parameter {} would be overwritten by replicas", k));
+                }
+                Ok(())
+            };
+            insert(config::REPLICA_IDENTIFIER, ParamValue::Int(replica as i64))?;
+            if let Some((seed_param, seed)) = base_seed {
+                insert(seed_param, ParamValue::Int(seed + replica as i64))?;
+                insert(
+                    config::REPLICA_SEED_PARAM_IDENTIFIER,
+                    ParamValue::Str(seed_param.into()),
+                )?;
+            }
+            result.push(replica_map);
+        }
+    }
+    Ok(result)
 }
 
 fn get_sim_params_from_substitutions(
     base: SimParams,
     substitutions: Vec<HashMap<String, ParamValue>>,
+    derived: &DerivedParams,
 ) -> Result<Vec<(usize, SimParams)>> {
     substitutions
         .iter()
@@ -206,6 +483,20 @@ fn get_sim_params_from_substitutions(
                     return Err(anyhow!("Found (non-special) parameter in substitutions that does not appear in parameter files: {}", k));
                 }
             }
+            new_sim
+                .convert_quantities()
+                .with_context(|| format!("While converting values with units of sim {}", i))?;
+            let derived_values = derived
+                .evaluate(&new_sim)
+                .with_context(|| format!("While computing derived parameters of sim {}", i))?;
+            for (k, v) in derived_values.iter() {
+                if new_sim.insert(k, v).is_none() && !is_special_param(k) {
+                    return Err(anyhow!("Found (non-special) derived parameter that does not appear in parameter files: {}", k));
+                }
+            }
+            new_sim
+                .resolve_templates()
+                .with_context(|| format!("While resolving string parameters of sim {}", i))?;
             Ok((i as usize, new_sim))
         })
         .collect()
@@ -241,6 +532,89 @@ fn get_parameter_groups(
     param_groups
 }
 
+/// The file name of the bob file of a named sim set or of the default sim set.
+pub fn get_bob_file_name(set_name: Option<&str>) -> String {
+    match set_name {
+        Some(name) => format!(
+            "{}{}{}",
+            config::NAMED_BOB_CONFIG_PREFIX,
+            name,
+            config::NAMED_BOB_CONFIG_SUFFIX
+        ),
+        None => config::DEFAULT_BOB_CONFIG_NAME.into(),
+    }
+}
+
+/// The names of all named sim sets in the input folder, sorted alphabetically.
+pub fn get_sim_set_names(folder: &Utf8Path) -> Result<Vec<String>> {
+    Ok(get_files(folder)?
+        .iter()
+        .filter_map(|file| {
+            file.file_name()?
+                .strip_prefix(config::NAMED_BOB_CONFIG_PREFIX)?
+                .strip_suffix(config::NAMED_BOB_CONFIG_SUFFIX)
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_owned())
+        })
+        .sorted()
+        .collect())
+}
+
+/// Returns the path of the bob file of the sim set, or an error listing
+/// the available sim sets if it does not exist.
+pub fn find_bob_file(folder: &Utf8Path, set_name: Option<&str>) -> Result<Utf8PathBuf> {
+    let path = folder.join(get_bob_file_name(set_name));
+    if path.is_file() {
+        return Ok(path);
+    }
+    let names = get_sim_set_names(folder)?;
+    match (set_name, names.is_empty()) {
+        (_, true) => Err(anyhow!("No bob file found at {}", path)),
+        (None, false) => Err(anyhow!(
+            "No {} in {}. Select one of the sim sets {} with --set-file",
+            config::DEFAULT_BOB_CONFIG_NAME,
+            folder,
+            names.join(", ")
+        )),
+        (Some(name), false) => Err(anyhow!(
+            "No sim set named {} in {}. Available sim sets: {}",
+            name,
+            folder,
+            names.join(", ")
+        )),
+    }
+}
+
+/// Keeps the sims whose index is selected (if a selection is given) and for which
+/// the condition holds (if one is given). The indices of the remaining sims are unchanged.
+pub fn filter_sim_set(
+    sim_set: SimSet,
+    select: Option<&Vec<usize>>,
+    condition: Option<&Expr>,
+) -> Result<SimSet> {
+    let mut simulations = vec![];
+    for (num, sim) in sim_set.into_iter() {
+        let selected = select
+            .map(|selected_sims| selected_sims.contains(&num))
+            .unwrap_or(true);
+        if selected && sim_matches(&sim, condition)? {
+            simulations.push((num, sim));
+        }
+    }
+    Ok(SimSet { simulations })
+}
+
+/// Whether the condition holds for the sim. Parameters which the sim
+/// does not have are treated as None.
+fn sim_matches(sim: &SimParams, condition: Option<&Expr>) -> Result<bool> {
+    match condition {
+        None => Ok(true),
+        Some(condition) => condition
+            .eval_bool(&mut |param| Ok(sim.get(param).cloned().unwrap_or(ParamValue::None)))
+            .with_context(|| format!("While evaluating selection for sim at {}", sim.folder)),
+    }
+}
+
 pub fn get_substitutions_cartesian(
     substitutions: &HashMap<String, Value>,
     grouped_params: Option<Vec<Vec<String>>>,
@@ -317,6 +691,7 @@ mod tests {
     use serde_yaml::to_value;
 
     use super::*;
+    use crate::test_utils::sim_params_with;
     #[test]
     fn normal_sim_set() -> Result<()> {
         let mut substitutions = HashMap::new();
@@ -354,6 +729,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn cartesian_overrides() -> Result<()> {
+        let mut config: SimSetConfig = serde_yaml::from_str(
+            "cartesian_type: NoCartesian\nsubstitutions:\n  a: [1, 2]\n  b: [3, 4]\n",
+        )?;
+        config.apply_overrides(&["c=5,6".parse()?, "b=7,8".parse()?], true)?;
+        let groups = match &config.cartesian_type {
+            CartesianType::Grouped(groups) => groups.clone(),
+            _ => panic!("Expected grouped cartesian type"),
+        };
+        let s = get_substitutions_cartesian(&config.substitutions, Some(groups))?;
+        assert_eq!(s.len(), 8);
+        assert_eq!(s[0]["a"], ParamValue::Int(1));
+        assert_eq!(s[0]["b"], ParamValue::Int(7));
+        assert_eq!(s[0]["c"], ParamValue::Int(5));
+        Ok(())
+    }
+
+    #[test]
+    fn grouped_overrides_without_cartesian() -> Result<()> {
+        let config = || -> Result<SimSetConfig> {
+            Ok(serde_yaml::from_str(
+                "cartesian_type:\n  Grouped:\n    - [a, b]\nsubstitutions:\n  a: [1, 2]\n  b: [3, 4]\n",
+            )?)
+        };
+        config()?.apply_overrides(&["b=7,8".parse()?], false)?;
+        config()?.apply_overrides(&["c=5".parse()?], false)?;
+        assert!(config()?
+            .apply_overrides(&["c=5,6".parse()?], false)
+            .is_err());
+        config()?.apply_overrides(&["c=5,6".parse()?], true)?;
+        Ok(())
+    }
+
     #[test]
     fn cartesian_sim_set_config_parameter_groups() -> Result<()> {
         let mut substitutions = HashMap::new();
@@ -402,4 +811,22 @@ mod tests {
         .is_err());
         Ok(())
     }
+
+    #[test]
+    fn replicas() -> Result<()> {
+        let base = sim_params_with(&[("a", ParamValue::Int(0)), ("seed", ParamValue::Int(10))]);
+        let mut substitutions = HashMap::new();
+        substitutions.insert("a".to_owned(), to_value([1, 2])?);
+        let s = get_substitutions_cartesian(&substitutions, None)?;
+        let s = get_substitutions_replicas(s, 3, Some("seed"), &base)?;
+        assert_eq!(s.len(), 6);
+        assert_eq!(s[4]["a"], ParamValue::Int(2));
+        assert_eq!(s[4]["seed"], ParamValue::Int(11));
+        assert_eq!(s[4][config::REPLICA_IDENTIFIER], ParamValue::Int(1));
+        let simulations = get_sim_params_from_substitutions(base, s, &DerivedParams::default())?;
+        let ensembles = SimSet { simulations }.ensembles();
+        assert_eq!(ensembles.len(), 2);
+        assert_eq!(ensembles[0].len(), 3);
+        Ok(())
+    }
 }
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::param_override::ParamOverride;
+use crate::param_value::ParamValue;
+use crate::sim_params::SimParams;
+use crate::sim_set::SimSet;
+use crate::util::read_file_contents;
+use crate::util::write_file;
+
+/// Describes the simulations in an output folder and where they came from,
+/// so that the sims can be found again independently of the names of their
+/// folders and without reading all of their parameter files.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SimSetManifest {
+    #[serde(default)]
+    pub input_folder: Option<Utf8PathBuf>,
+    /// The name of the sim set if it was defined in a sims.<name>.bob file
+    #[serde(default)]
+    pub set_name: Option<String>,
+    /// The substitutions given on the command line with --set
+    #[serde(default)]
+    pub overrides: Vec<ParamOverride>,
+    /// Whether the overrides were combined with --cartesian
+    #[serde(default)]
+    pub cartesian: bool,
+    #[serde(default)]
+    pub bob_file: Option<String>,
+    #[serde(default)]
+    pub cartesian_type: Option<String>,
+    #[serde(default)]
+    pub bob_version: String,
+    /// Seconds since the unix epoch at which the sim set was written
+    #[serde(default)]
+    pub timestamp: u64,
+    pub sims: Vec<ManifestEntry>,
+}
+
+/// How a sim set was created from the input folder: the bob file it is
+/// defined in and the substitutions given on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct SimSetSource {
+    pub set_name: Option<String>,
+    pub overrides: Vec<ParamOverride>,
+    pub cartesian: bool,
+    /// The contents of the bob file as it was read to create the sims
+    pub bob_file: Option<String>,
+    pub cartesian_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    pub index: usize,
+    pub name: String,
+    /// The values of the parameters that vary within the sim set
+    #[serde(default)]
+    pub varying_params: BTreeMap<String, ParamValue>,
+}
+
+impl ManifestEntry {
+    pub fn new(index: usize, name: String, sim: &SimParams, sim_set: &SimSet) -> ManifestEntry {
+        let varying_params = sim
+            .iter()
+            .filter(|(k, _)| sim_set.varies(k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        ManifestEntry {
+            index,
+            name,
+            varying_params,
+        }
+    }
+}
+
+impl SimSetManifest {
+    pub fn new(
+        input_folder: &Utf8Path,
+        source: &SimSetSource,
+        sims: Vec<ManifestEntry>,
+    ) -> Result<SimSetManifest> {
+        Ok(SimSetManifest {
+            input_folder: Some(
+                input_folder
+                    .canonicalize()
+                    .ok()
+                    .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+                    .unwrap_or_else(|| input_folder.to_owned()),
+            ),
+            set_name: source.set_name.clone(),
+            overrides: source.overrides.clone(),
+            cartesian: source.cartesian,
+            bob_file: source.bob_file.clone(),
+            cartesian_type: source.cartesian_type.clone(),
+            bob_version: env!("CARGO_PKG_VERSION").to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            sims,
+        })
+    }
+
+    pub fn read(output_folder: &Utf8Path) -> Result<Option<SimSetManifest>> {
+        let path = get_manifest_path(output_folder);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = read_file_contents(&path)?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("While reading sim set manifest at {:?}", path))
+            .map(Some)
+    }
+
+    pub fn write(&self, output_folder: &Utf8Path) -> Result<()> {
+        let contents = serde_yaml::to_string(self)?;
+        write_file(&get_manifest_path(output_folder), &contents)
+    }
+}
+
+pub fn get_manifest_path(output_folder: &Utf8Path) -> Utf8PathBuf {
+    output_folder.join(config::DEFAULT_SIM_SET_MANIFEST_NAME)
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::expression::Expr;
+use crate::param_value::ParamValue;
+use crate::sim_params::SimParams;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Exclude,
+    IncludeOnly,
+}
+
+impl std::fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterKind::Exclude => write!(f, "exclude"),
+            FilterKind::IncludeOnly => write!(f, "include_only"),
+        }
+    }
+}
+
+/// A predicate from the `exclude` or `include_only` section of the bob file
+/// which removes parameter combinations from the sim set.
+#[derive(Debug, Clone)]
+pub struct SubstitutionFilter {
+    kind: FilterKind,
+    source: String,
+    expr: Expr,
+}
+
+/// The number of parameter combinations that were removed by a filter.
+#[derive(Debug, Clone)]
+pub struct FilterReport {
+    pub kind: FilterKind,
+    pub source: String,
+    pub num_removed: usize,
+}
+
+impl std::fmt::Display for FilterReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} \"{}\": removed {} combinations",
+            self.kind, self.source, self.num_removed
+        )
+    }
+}
+
+impl SubstitutionFilter {
+    pub fn new(kind: FilterKind, source: &str) -> Result<SubstitutionFilter> {
+        let expr = source
+            .parse()
+            .with_context(|| format!("While parsing {} rule: {}", kind, source))?;
+        Ok(SubstitutionFilter {
+            kind,
+            source: source.to_owned(),
+            expr,
+        })
+    }
+
+    pub fn from_rules(
+        exclude: &[String],
+        include_only: &[String],
+    ) -> Result<Vec<SubstitutionFilter>> {
+        exclude
+            .iter()
+            .map(|rule| Self::new(FilterKind::Exclude, rule))
+            .chain(
+                include_only
+                    .iter()
+                    .map(|rule| Self::new(FilterKind::IncludeOnly, rule)),
+            )
+            .collect()
+    }
+
+    /// Whether the parameter combination given by the substitutions
+    /// (on top of the parameters of the base sim) is kept by this filter.
+    pub fn keeps(
+        &self,
+        substitutions: &HashMap<String, ParamValue>,
+        base: &SimParams,
+    ) -> Result<bool> {
+        let matches = self
+            .expr
+            .eval_bool(&mut |param| {
+                substitutions
+                    .get(param)
+                    .or_else(|| base.get(param))
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Unknown parameter {}", param))
+            })
+            .with_context(|| format!("While evaluating {} rule: {}", self.kind, self.source))?;
+        Ok(match self.kind {
+            FilterKind::Exclude => !matches,
+            FilterKind::IncludeOnly => matches,
+        })
+    }
+}
+
+/// Removes all parameter combinations that are rejected by one of the filters.
+/// Each removed combination is counted towards the first filter that rejected it.
+/// Removing every combination is an error, since it is almost certainly a mistake.
+pub fn apply_filters(
+    filters: &[SubstitutionFilter],
+    substitutions: &mut Vec<HashMap<String, ParamValue>>,
+    base: &SimParams,
+) -> Result<Vec<FilterReport>> {
+    let mut reports: Vec<FilterReport> = filters
+        .iter()
+        .map(|filter| FilterReport {
+            kind: filter.kind,
+            source: filter.source.clone(),
+            num_removed: 0,
+        })
+        .collect();
+    let mut kept = vec![];
+    'outer: for substitution_map in substitutions.drain(..) {
+        for (filter, report) in filters.iter().zip(reports.iter_mut()) {
+            if !filter.keeps(&substitution_map, base)? {
+                report.num_removed += 1;
+                continue 'outer;
+            }
+        }
+        kept.push(substitution_map);
+    }
+    let num_removed: usize = reports.iter().map(|report| report.num_removed).sum();
+    if kept.is_empty() && num_removed > 0 {
+        return Err(anyhow!(
+            "The exclude/include_only rules removed all {} parameter combinations:\n{}",
+            num_removed,
+            reports
+                .iter()
+                .map(|report| report.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+    *substitutions = kept;
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sim_params_with;
+
+    fn base_sim() -> SimParams {
+        sim_params_with(&[("c", ParamValue::Int(0))])
+    }
+
+    /// All combinations of a in 1..=3 and b in 1..=3
+    fn substitutions() -> Vec<HashMap<String, ParamValue>> {
+        (1..=3)
+            .flat_map(|a| {
+                (1..=3).map(move |b| {
+                    [
+                        ("a".to_string(), ParamValue::Int(a)),
+                        ("b".to_string(), ParamValue::Int(b)),
+                    ]
+                    .into_iter()
+                    .collect()
+                })
+            })
+            .collect()
+    }
+
+    /// The kept (a, b) combinations and the number removed by each filter
+    type Filtered = (Vec<(i64, i64)>, Vec<usize>);
+
+    fn filter(exclude: &[&str], include_only: &[&str]) -> Result<Filtered> {
+        let to_strings = |rules: &[&str]| rules.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        let filters =
+            SubstitutionFilter::from_rules(&to_strings(exclude), &to_strings(include_only))?;
+        let mut substitutions = substitutions();
+        let reports = apply_filters(&filters, &mut substitutions, &base_sim())?;
+        let kept = substitutions
+            .iter()
+            .map(|s| (s["a"].unwrap_i64(), s["b"].unwrap_i64()))
+            .collect();
+        Ok((kept, reports.iter().map(|r| r.num_removed).collect()))
+    }
+
+    #[test]
+    fn exclude() -> Result<()> {
+        let (kept, removed) = filter(&["a == b"], &[])?;
+        assert_eq!(kept, [(1, 2), (1, 3), (2, 1), (2, 3), (3, 1), (3, 2)]);
+        assert_eq!(removed, [3]);
+        Ok(())
+    }
+
+    #[test]
+    fn include_only() -> Result<()> {
+        let (kept, removed) = filter(&[], &["a == 1 && b > c + 1"])?;
+        assert_eq!(kept, [(1, 2), (1, 3)]);
+        assert_eq!(removed, [7]);
+        Ok(())
+    }
+
+    #[test]
+    fn exclude_and_include_only() -> Result<()> {
+        let (kept, removed) = filter(&["b == 3"], &["a < 3"])?;
+        assert_eq!(kept, [(1, 1), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(removed, [3, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn removing_everything_is_an_error() {
+        assert!(filter(&["a > 0"], &[]).is_err());
+        assert!(filter(&["a > 3"], &[]).is_ok());
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+
+use camino::Utf8Path;
+
+use crate::param_value::ParamValue;
+use crate::sim_params::SimParams;
+use crate::sim_params::SimParamsKind;
+
+/// Input sim params in the current folder with unit units, containing the
+/// given params in addition to the ones every sim requires.
+pub fn sim_params_with(params: &[(&str, ParamValue)]) -> SimParams {
+    sim_params_in(Utf8Path::new("."), SimParamsKind::Input, params)
+}
+
+/// Like sim_params_with, but in the given folder and of the given kind.
+/// The given params take precedence over the required ones.
+pub fn sim_params_in(
+    folder: &Utf8Path,
+    kind: SimParamsKind,
+    params: &[(&str, ParamValue)],
+) -> SimParams {
+    let required = [
+        ("UnitLength_in_cm", ParamValue::new_float(1.0)),
+        ("UnitVelocity_in_cm_per_s", ParamValue::new_float(1.0)),
+        ("UnitMass_in_g", ParamValue::new_float(1.0)),
+        ("TimeLimitCPU", ParamValue::new_float(1.0)),
+    ];
+    let params: HashMap<String, ParamValue> = required
+        .iter()
+        .chain(params.iter())
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect();
+    SimParams::new(folder, params, kind).unwrap()
+}
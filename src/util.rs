@@ -116,6 +116,19 @@ pub fn get_folders(folder: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
     Ok(iter_folders(folder)?.collect())
 }
 
+/// The commit currently checked out in the git repository at the folder.
+pub fn get_git_commit(folder: &Utf8Path) -> Option<String> {
+    Command::new("git")
+        .arg("-C")
+        .arg(folder.as_str())
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_owned())
+}
+
 #[derive(Debug)]
 pub struct ShellCommandOutput {
     pub success: bool,
@@ -0,0 +1,122 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+
+use crate::config;
+use crate::util::read_file_contents;
+
+/// Reads a yaml file and resolves its include key, which names one file or a list
+/// of files relative to the including file. The included files are merged in order,
+/// so that later files override earlier ones and the including file overrides all of
+/// them. Top-level keys are replaced as a whole, except for those in `merged_keys`,
+/// whose mappings are merged entry by entry.
+pub fn read_yaml_with_includes(path: &Utf8Path, merged_keys: &[&str]) -> Result<Value> {
+    read_with_includes(path, merged_keys, &mut vec![])
+}
+
+fn read_with_includes(
+    path: &Utf8Path,
+    merged_keys: &[&str],
+    included_from: &mut Vec<Utf8PathBuf>,
+) -> Result<Value> {
+    let canonical_path = path
+        .canonicalize()
+        .ok()
+        .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+        .unwrap_or_else(|| path.to_owned());
+    if included_from.contains(&canonical_path) {
+        return Err(anyhow!(
+            "Include cycle: {} -> {}",
+            included_from
+                .iter()
+                .map(|p| p.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+            canonical_path
+        ));
+    }
+    let contents = read_file_contents(path)?;
+    let mut value: Value =
+        serde_yaml::from_str(&contents).with_context(|| format!("While reading {}", path))?;
+    let includes = match &mut value {
+        Value::Mapping(mapping) => mapping.remove(&Value::String(config::INCLUDE_KEY.into())),
+        _ => None,
+    };
+    let include_paths = match includes {
+        None => return Ok(value),
+        Some(includes) => get_include_paths(includes)
+            .with_context(|| format!("Invalid {} in {}", config::INCLUDE_KEY, path))?,
+    };
+    let folder = path.parent().unwrap_or_else(|| Utf8Path::new("."));
+    included_from.push(canonical_path);
+    let mut merged = Value::Mapping(Mapping::new());
+    for include_path in include_paths {
+        let include_path = folder.join(include_path);
+        let included = read_with_includes(&include_path, merged_keys, included_from)
+            .with_context(|| format!("While including {} from {}", include_path, path))?;
+        merge(&mut merged, included, merged_keys)
+            .with_context(|| format!("While including {} from {}", include_path, path))?;
+    }
+    included_from.pop();
+    merge(&mut merged, value, merged_keys).with_context(|| format!("While reading {}", path))?;
+    Ok(merged)
+}
+
+fn get_include_paths(includes: Value) -> Result<Vec<String>> {
+    let to_path = |value: Value| match value {
+        Value::String(path) => Ok(path),
+        value => Err(anyhow!("Expected a file name, found {:?}", value)),
+    };
+    match includes {
+        Value::Sequence(paths) => paths.into_iter().map(to_path).collect(),
+        value => Ok(vec![to_path(value)?]),
+    }
+}
+
+/// Merges `other` into `base`, with the entries of `other` taking precedence.
+fn merge(base: &mut Value, other: Value, merged_keys: &[&str]) -> Result<()> {
+    let (base, other) = match (base, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => (base, other),
+        _ => return Err(anyhow!("Files with includes must contain a mapping")),
+    };
+    for (key, value) in other {
+        let merge_entries = matches!(key.as_str(), Some(key) if merged_keys.contains(&key));
+        match (base.get_mut(&key), value) {
+            (Some(Value::Mapping(existing)), Value::Mapping(entries)) if merge_entries => {
+                for (entry_key, entry_value) in entries {
+                    existing.insert(entry_key, entry_value);
+                }
+            }
+            (Some(existing), value) => *existing = value,
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_with_precedence() -> Result<()> {
+        let mut base: Value = serde_yaml::from_str(
+            "cartesian_type: All\nsubstitutions:\n  a: 1\n  b: 2\nexclude: [a==1]\n",
+        )?;
+        let other: Value = serde_yaml::from_str(
+            "cartesian_type: NoCartesian\nsubstitutions:\n  b: 3\n  c: 4\nexclude: [b==3]\n",
+        )?;
+        merge(&mut base, other, &["substitutions"])?;
+        let expected: Value = serde_yaml::from_str(
+            "cartesian_type: NoCartesian\nsubstitutions:\n  a: 1\n  b: 3\n  c: 4\nexclude: [b==3]\n",
+        )?;
+        assert_eq!(base, expected);
+        Ok(())
+    }
+}